@@ -47,7 +47,6 @@
           libxkbcommon
           libgcc.lib
         ];
-        shadersCompilePath = "$HOME/.cache/rust-gpu-shaders";
         sandsim = rustPlatform.buildRustPackage {
           pname = "sandsim";
           version = "0.0.0";
@@ -63,27 +62,24 @@
             # HACK(eddyb) bypass cargoSetupPostPatchHook.
             export cargoDepsCopy="$cargoDeps"
           '';
+          # The default features embed the shaders, compiled by the build script with
+          # the toolchain above, so the package needs neither at runtime
           nativeBuildInputs = [pkgs.makeWrapper];
-          configurePhase = ''
-            export SHADERS_DIR="$out/repo/shaders"
-            export SHADERS_TARGET_DIR=${shadersCompilePath}
-          '';
           fixupPhase = ''
-            cp -r . $out/repo
             wrapProgram $out/bin/runner \
-              --set LD_LIBRARY_PATH $LD_LIBRARY_PATH:$out/lib:${nixpkgs.lib.makeLibraryPath buildInputs} \
-              --set PATH $PATH:${nixpkgs.lib.makeBinPath [rustPkg]}
+              --set LD_LIBRARY_PATH $LD_LIBRARY_PATH:$out/lib:${nixpkgs.lib.makeLibraryPath buildInputs}
           '';
         };
       in rec {
         packages.default = pkgs.writeShellScriptBin "sandsim" ''
-          export CARGO_TARGET_DIR="${shadersCompilePath}"
           exec -a "$0" "${sandsim}/bin/runner" "$@"
         '';
         apps.default = {
           type = "app";
           program = "${packages.default}/bin/sandsim";
         };
+        # Hot reloading builds the shaders at runtime with the nightly toolchain, so it is
+        # only for development: `cargo run --features hot-reload`
         devShells.default = with pkgs;
           mkShell {
             nativeBuildInputs = [rustPkg];
@@ -0,0 +1,132 @@
+//! Compares submitting every simulation step on its own against recording all steps of a
+//! frame into one encoder, the way `Simulation::step` does for the runner.
+//!
+//! Both run `Simulation::step` with the shaders of the runner, either once per sub-step
+//! or once for all of them. The `tiling` group times the tiled shader against the
+//! untiled one on the same steps. Run with `cargo bench -p runner`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use runner::simulation::Simulation;
+use shared::{grid::Grid, particle::Particle};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const SIZE: usize = 1024;
+const STEPS: [u32; 3] = [1, 16, 64];
+
+struct Gpu {
+    device: Arc<wgpu::Device>,
+    simulation: Simulation,
+    /// Every other row filled with sand, so that the steps have work to do
+    grid: Grid<Particle>,
+}
+
+impl Gpu {
+    fn new() -> Option<Self> {
+        let instance = wgpu::Instance::default();
+        let adapter = futures::executor::block_on(
+            instance.request_adapter(&wgpu::RequestAdapterOptions::default()),
+        )?;
+        // Push constants where available, as the runner uses them
+        let features = adapter.features() & wgpu::Features::PUSH_CONSTANTS;
+        let limits = wgpu::Limits {
+            max_push_constant_size: adapter.limits().max_push_constant_size.min(128),
+            ..wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits())
+        };
+        let (device, queue) = futures::executor::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                required_features: features,
+                required_limits: limits,
+                memory_hints: Default::default(),
+            },
+            None,
+        ))
+        .ok()?;
+        let device = Arc::new(device);
+
+        let grid = Grid::from_fn(SIZE, SIZE, |_, y| {
+            if y % 2 == 0 {
+                Particle::new_sand()
+            } else {
+                Particle::default()
+            }
+        });
+        let simulation = Simulation::on_gpu(device.clone(), Arc::new(queue), &grid)
+            .map_err(|err| eprintln!("{err}"))
+            .ok()?;
+        Some(Self {
+            device,
+            simulation,
+            grid,
+        })
+    }
+
+    /// Time of `iters` runs of `run`, each from the initial grid and until the device
+    /// finished the steps
+    fn time(&mut self, iters: u64, run: impl Fn(&mut Simulation)) -> Duration {
+        let mut total = Duration::ZERO;
+        for _ in 0..iters {
+            self.simulation.set_cells(&self.grid).unwrap();
+            self.device.poll(wgpu::Maintain::Wait);
+            let start = Instant::now();
+            run(&mut self.simulation);
+            self.device.poll(wgpu::Maintain::Wait);
+            total += start.elapsed();
+        }
+        total
+    }
+}
+
+fn submit(c: &mut Criterion) {
+    let Some(mut gpu) = Gpu::new() else {
+        eprintln!("No adapter found, skipping");
+        return;
+    };
+    let mut group = c.benchmark_group("steps");
+    group.sample_size(20);
+    for steps in STEPS {
+        // One encoder and submission per step
+        group.bench_with_input(BenchmarkId::new("separate", steps), &steps, |b, &steps| {
+            b.iter_custom(|iters| {
+                gpu.time(iters, |simulation| {
+                    for _ in 0..steps {
+                        simulation.step(1);
+                    }
+                })
+            })
+        });
+        // All steps in one encoder and submission
+        group.bench_with_input(BenchmarkId::new("batched", steps), &steps, |b, &steps| {
+            b.iter_custom(|iters| gpu.time(iters, |simulation| simulation.step(steps)))
+        });
+    }
+    group.finish();
+}
+
+fn tiling(c: &mut Criterion) {
+    let Some(mut gpu) = Gpu::new() else {
+        eprintln!("No adapter found, skipping");
+        return;
+    };
+    let mut group = c.benchmark_group("tiling");
+    group.sample_size(20);
+    for steps in STEPS {
+        for (name, tiled) in [("untiled", false), ("tiled", true)] {
+            group.bench_with_input(BenchmarkId::new(name, steps), &steps, |b, &steps| {
+                b.iter_custom(|iters| {
+                    gpu.time(iters, |simulation| {
+                        simulation.set_tiled(tiled);
+                        simulation.step(steps);
+                    })
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, submit, tiling);
+criterion_main!(benches);
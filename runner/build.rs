@@ -7,12 +7,12 @@ fn main() -> Result<(), Box<dyn Error>> {
     println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rerun-if-env-changed=CARGO_CFG_TARGET_OS");
     println!("cargo:rerun-if-env-changed=CARGO_CFG_TARGET_ARCH");
-    // While OUT_DIR is set for both build.rs and compiling the crate, PROFILE is only set in
-    // build.rs. So, export it to crate compilation as well.
     let profile = env::var("PROFILE").unwrap();
-    println!("cargo:rustc-env=PROFILE={profile}");
-    if target_arch != "wasm32" {
-        return Ok(());
+    println!("cargo::rustc-check-cfg=cfg(hot_reload)");
+    // The web build cannot hot reload, since spirv-builder cannot run there. Otherwise the
+    // shaders are still embedded, to start from, along with the names of their entry points.
+    if env::var_os("CARGO_FEATURE_HOT_RELOAD").is_some() && target_arch != "wasm32" {
+        println!("cargo:rustc-cfg=hot_reload");
     }
     let mut dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
     // Strip `$profile/build/*/out`.
@@ -29,15 +29,13 @@ fn main() -> Result<(), Box<dyn Error>> {
     // otherwise repeated `cargo build`s will cause build script reruns and the
     // rebuilding of `rustc_codegen_spirv` (likely due to common proc macro deps).
     let dir = dir.join("builder");
-    let status = std::process::Command::new("cargo")
-        .args([
-            "run",
-            "--release",
-            "-p",
-            "builder",
-            "--target-dir",
-        ])
-        .arg(dir)
+    let mut command = std::process::Command::new("cargo");
+    command.args(["run", "--release", "-p", "builder", "--target-dir"]);
+    command.arg(dir);
+    if env::var_os("CARGO_FEATURE_USE_INSTALLED_TOOLS").is_some() {
+        command.args(["--no-default-features", "--features", "use-installed-tools"]);
+    }
+    let status = command
         .env_remove("CARGO_ENCODED_RUSTFLAGS")
         .stderr(std::process::Stdio::inherit())
         .stdout(std::process::Stdio::inherit())
@@ -0,0 +1,38 @@
+//! Compiles the shaders ahead of time, for builds without hot reloading and for the web
+//! where `spirv-builder` cannot run. Started by the runner's build script, which passes
+//! on its `OUT_DIR` and forwards the `cargo:` lines printed here.
+//!
+//! Both variants of the shaders are written to `OUT_DIR`, `sandsim.spv` with push
+//! constants and `sandsim-constant-buffer.spv` with the constants in a buffer, so that the
+//! runner can pick one once it knows the adapter.
+
+use spirv_builder::{MetadataPrintout, SpirvBuilder};
+use std::error::Error;
+use std::path::PathBuf;
+
+const VARIANTS: [(&str, &[&str]); 2] = [
+    ("sandsim.spv", &[]),
+    ("sandsim-constant-buffer.spv", &["constant-buffer"]),
+];
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let crate_path = [env!("CARGO_MANIFEST_DIR"), "..", "..", "shaders", "sandsim"]
+        .iter()
+        .copied()
+        .collect::<PathBuf>();
+    let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").ok_or("OUT_DIR is not set")?);
+
+    // The same for both variants
+    let mut entry_points = String::new();
+    for (file, features) in VARIANTS {
+        let variant = SpirvBuilder::new(&crate_path, "spirv-unknown-vulkan1.1")
+            .print_metadata(MetadataPrintout::DependencyOnly)
+            .shader_crate_features(features.iter().map(|feature| feature.to_string()))
+            .build()?;
+        // Both variants are built to the same path, so copy each before the next build
+        std::fs::copy(variant.module.unwrap_single(), out_dir.join(file))?;
+        entry_points = variant.codegen_entry_point_strings();
+    }
+    std::fs::write(out_dir.join("entry_points.rs"), entry_points)?;
+    Ok(())
+}
@@ -1,8 +1,9 @@
 use crate::{
-    context::GraphicsContext,
+    context::{ContextError, GraphicsContext},
     controller::Controller,
     render_pass::RenderPass,
-    shader::CompiledShaderModules,
+    settings::Settings,
+    shader::{self, CompiledShaderModules},
     ui::{Ui, UiState},
     user_event::UserEvent,
     Options,
@@ -13,8 +14,7 @@ use egui_winit::winit::{
     event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
     event_loop::{ActiveEventLoop, EventLoopProxy},
     keyboard::{Key, NamedKey},
-    platform::wayland::*,
-    window::{Window, WindowId},
+    window::{Fullscreen, Window, WindowId},
 };
 use std::sync::Arc;
 
@@ -25,12 +25,16 @@ pub struct Graphics {
     ui: Ui,
     ui_state: UiState,
     window: Arc<Window>,
+    options: Options,
 }
 
 pub struct Builder {
     event_proxy: EventLoopProxy<UserEvent>,
     compiled_shader_modules: CompiledShaderModules,
+    /// The latest shader build, if it failed before the window existed to show it
+    shader_build_error: Option<String>,
     options: Options,
+    settings: Settings,
 }
 
 pub enum App {
@@ -48,7 +52,9 @@ impl App {
         Self::Builder(Builder {
             event_proxy,
             compiled_shader_modules,
+            shader_build_error: None,
             options,
+            settings: Settings::load(),
         })
     }
 
@@ -60,7 +66,6 @@ impl App {
             gfx.ctx.config.width = size.width;
             gfx.ctx.config.height = size.height;
             gfx.ctx.surface.configure(&gfx.ctx.device, &gfx.ctx.config);
-            gfx.controller.resize(size);
         }
     }
 
@@ -85,6 +90,13 @@ impl App {
         gfx.controller.mouse_move(position);
     }
 
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        let Self::Graphics(gfx) = self else {
+            return;
+        };
+        gfx.controller.set_scale_factor(scale_factor);
+    }
+
     pub fn mouse_scroll(&mut self, delta: MouseScrollDelta) {
         let Self::Graphics(gfx) = self else {
             return;
@@ -96,12 +108,17 @@ impl App {
         let Self::Graphics(gfx) = self else {
             return;
         };
-        for _ in 0..gfx.controller.iterations() {
-            gfx.controller.pre_update();
-            gfx.rpass
-                .compute(&gfx.ctx, &gfx.window.inner_size(), &mut gfx.controller);
-            gfx.controller.post_update();
+        if gfx.controller.update_grid_size() {
+            gfx.rpass.resize_grid(&gfx.ctx, &gfx.controller);
+        }
+        if let Some(reset) = gfx.controller.take_reset() {
+            gfx.rpass.reset_grid(&gfx.controller, reset);
+        }
+        if gfx.controller.take_validate_tiled() {
+            gfx.rpass.validate_tiled(&mut gfx.controller);
         }
+        let iterations = gfx.controller.iterations();
+        gfx.rpass.compute(&mut gfx.controller, iterations);
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
@@ -109,14 +126,44 @@ impl App {
             return Ok(());
         };
         gfx.window.request_redraw();
+        let ui_output = gfx
+            .ui
+            .prepare(&gfx.window, &mut gfx.ui_state, &mut gfx.controller);
         gfx.controller.pre_render();
-        gfx.rpass.render(
-            &gfx.ctx,
-            &gfx.window,
-            &mut gfx.ui,
-            &mut gfx.ui_state,
-            &mut gfx.controller,
-        )
+        gfx.rpass.render(&gfx.ctx, ui_output, &mut gfx.controller)?;
+        if let Some(timings) = gfx.rpass.read_timings(&gfx.ctx) {
+            gfx.ui_state.gpu_times.push(timings);
+        }
+        let profiling = gfx.ui_state.show_gpu_times && gfx.ui_state.gpu_times_supported;
+        if !profiling {
+            gfx.ui_state.gpu_times.clear();
+        }
+        gfx.rpass.set_profiling(profiling);
+        Ok(())
+    }
+
+    fn device_lost(&self) -> bool {
+        matches!(self, Self::Graphics(gfx) if gfx.ctx.is_lost())
+    }
+
+    /// Recreates the device and everything on it, restoring the grid from its last
+    /// backup. Exits if there is no device to be had anymore.
+    pub fn recover_device(&mut self, event_loop: &ActiveEventLoop) {
+        if cfg!(target_arch = "wasm32") {
+            // The browser cannot wait for the new device
+            panic!("Device lost, reload the page");
+        }
+        let Self::Graphics(gfx) = std::mem::replace(self, Self::Building) else {
+            return;
+        };
+        eprintln!("Recreating the device");
+        match futures::executor::block_on(recreate_graphics(gfx)) {
+            Ok(gfx) => *self = Self::Graphics(gfx),
+            Err(err) => {
+                eprintln!("{err}");
+                event_loop.exit();
+            }
+        }
     }
 
     pub fn update_and_render(&mut self) -> Result<(), wgpu::SurfaceError> {
@@ -132,25 +179,71 @@ impl App {
     }
 
     pub fn new_module(&mut self, new_module: CompiledShaderModules) {
-        let Self::Graphics(gfx) = self else {
-            return;
+        let gfx = match self {
+            Self::Graphics(gfx) => gfx,
+            // Built before the window, replaces the embedded shaders it is created with
+            Self::Builder(builder) => {
+                builder.compiled_shader_modules = new_module;
+                builder.shader_build_error = None;
+                return;
+            }
+            Self::Building => return,
         };
-        gfx.rpass.new_module(&gfx.ctx, new_module);
+        // Built by the watcher of the other variant, which keeps running after
+        // `shader::rebuild` started one for this variant
+        if new_module.push_constants() != gfx.ctx.push_constants {
+            return;
+        }
+        match gfx.rpass.new_module(&gfx.ctx, new_module) {
+            Ok(()) => gfx.ui_state.shaders_reloaded(),
+            Err(err) => {
+                eprintln!("Failed to create pipelines: {err}");
+                gfx.ui_state.shader_reload_failed(err);
+            }
+        }
         gfx.window.request_redraw();
     }
 
+    pub fn shader_build_failed(&mut self, err: String) {
+        eprintln!("Failed to build shaders:\n{err}");
+        match self {
+            Self::Graphics(gfx) => gfx.ui_state.shader_reload_failed(err),
+            Self::Builder(builder) => builder.shader_build_error = Some(err),
+            Self::Building => {}
+        }
+    }
+
     pub fn set_vsync(&mut self, enable: bool) {
         let Self::Graphics(gfx) = self else {
             return;
         };
         gfx.ctx.set_vsync(enable);
     }
+
+    pub fn save_settings(&self) {
+        let Self::Graphics(gfx) = self else {
+            return;
+        };
+        Settings {
+            ui: gfx.ui_state.settings(),
+            controller: gfx.controller.settings(),
+        }
+        .save();
+    }
 }
 
 impl ApplicationHandler<UserEvent> for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         if let Self::Builder(builder) = std::mem::replace(self, Self::Building) {
-            futures::executor::block_on(create_graphics(builder, event_loop));
+            let window = create_window(event_loop, &builder.options);
+            cfg_if::cfg_if! {
+                if #[cfg(target_arch = "wasm32")] {
+                    // The browser cannot block on the adapter and device requests
+                    wasm_bindgen_futures::spawn_local(create_graphics(builder, window));
+                } else {
+                    futures::executor::block_on(create_graphics(builder, window));
+                }
+            }
         }
     }
 
@@ -165,6 +258,9 @@ impl ApplicationHandler<UserEvent> for App {
         }
         match event {
             WindowEvent::RedrawRequested => {
+                if self.device_lost() {
+                    self.recover_device(event_loop);
+                }
                 if let Err(wgpu::SurfaceError::OutOfMemory) = self.update_and_render() {
                     event_loop.exit()
                 }
@@ -184,60 +280,163 @@ impl ApplicationHandler<UserEvent> for App {
             WindowEvent::MouseInput { state, button, .. } => self.mouse_input(state, button),
             WindowEvent::MouseWheel { delta, .. } => self.mouse_scroll(delta),
             WindowEvent::CursorMoved { position, .. } => self.mouse_move(position),
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.set_scale_factor(scale_factor)
+            }
             _ => {}
         }
     }
 
-    fn user_event(&mut self, _event_loop: &ActiveEventLoop, event: UserEvent) {
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: UserEvent) {
         match event {
             UserEvent::CreateWindow(gfx) => {
                 gfx.window.request_redraw();
                 *self = Self::Graphics(gfx);
             }
+            UserEvent::InitFailed(err) => {
+                if cfg!(target_arch = "wasm32") {
+                    // Shown in the browser console by the panic hook
+                    panic!("{err}");
+                }
+                eprintln!("{err}");
+                event_loop.exit();
+            }
             UserEvent::NewModule(new_module) => self.new_module(new_module),
+            UserEvent::ShaderBuildFailed(err) => self.shader_build_failed(err),
             UserEvent::SetVSync(enable) => self.set_vsync(enable),
         }
     }
+
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        self.save_settings();
+    }
 }
 
-async fn create_graphics(builder: Builder, event_loop: &ActiveEventLoop) {
-    let window = Arc::new(
-        event_loop
-            .create_window(
-                Window::default_attributes()
-                    .with_title("sandsim")
-                    .with_name("sandsim", "")
-                    .with_inner_size(PhysicalSize::new(1280.0, 720.0)),
-            )
-            .unwrap(),
-    );
+fn create_window(event_loop: &ActiveEventLoop, options: &Options) -> Arc<Window> {
+    let attributes = Window::default_attributes()
+        .with_title("sandsim")
+        .with_inner_size(options.window_size)
+        .with_fullscreen(options.fullscreen.then_some(Fullscreen::Borderless(None)));
+    #[cfg(target_os = "linux")]
+    let attributes = {
+        use egui_winit::winit::platform::wayland::WindowAttributesExtWayland;
+        attributes.with_name("sandsim", "")
+    };
+    #[cfg(target_arch = "wasm32")]
+    let attributes = {
+        use egui_winit::winit::platform::web::WindowAttributesExtWebSys;
+        // Adds the canvas to the document body
+        attributes.with_append(true)
+    };
+    Arc::new(event_loop.create_window(attributes).unwrap())
+}
 
-    let ctx = GraphicsContext::new(window.clone(), &builder.options).await;
+async fn create_graphics(builder: Builder, window: Arc<Window>) {
+    let event_proxy = builder.event_proxy.clone();
+    let event = match try_create_graphics(builder, window).await {
+        Ok(gfx) => UserEvent::CreateWindow(gfx),
+        Err(err) => UserEvent::InitFailed(err),
+    };
+    event_proxy.send_event(event).ok();
+}
 
-    let ui = Ui::new(window.clone(), builder.event_proxy.clone());
+async fn try_create_graphics(
+    builder: Builder,
+    window: Arc<Window>,
+) -> Result<Graphics, ContextError> {
+    let mut ctx = GraphicsContext::new(window.clone(), &builder.options).await?;
+    let modules = modules_for(&ctx, &builder.options, builder.compiled_shader_modules);
 
-    let ui_state = UiState::new();
+    let ui = Ui::new(window.clone(), builder.event_proxy.clone());
 
-    let controller = Controller::new(window.inner_size(), &builder.options);
+    let mut ui_state = UiState::new(&builder.settings.ui);
+    if let Some(err) = builder.shader_build_error {
+        ui_state.shader_reload_failed(err);
+    }
+    if !ui_state.vsync {
+        ctx.set_vsync(false);
+    }
 
-    let rpass = RenderPass::new(
-        &ctx,
-        builder.compiled_shader_modules,
-        builder.options,
-        &controller.buffers(),
+    let mut controller = Controller::new(
+        window.inner_size(),
+        &builder.options,
+        &builder.settings.controller,
     );
+    controller.set_scale_factor(window.scale_factor());
+
+    let rpass =
+        RenderPass::new(&ctx, modules, &controller).map_err(ContextError::CreatePipelines)?;
+    ui_state.gpu_times_supported = rpass.profiling_supported();
+    ui_state.device_info = Some(ctx.device_info.clone());
 
-    let gfx = Graphics {
+    Ok(Graphics {
         rpass,
         ctx,
         controller,
         ui,
         ui_state,
         window,
-    };
+        options: builder.options,
+    })
+}
 
-    builder
-        .event_proxy
-        .send_event(UserEvent::CreateWindow(gfx))
-        .ok();
+/// `modules` in the variant for the constants of `ctx`, or the embedded ones if they are
+/// in the other variant. With hot reloading, the shaders are then rebuilt in that variant.
+fn modules_for(
+    ctx: &GraphicsContext,
+    options: &Options,
+    modules: CompiledShaderModules,
+) -> CompiledShaderModules {
+    if modules.push_constants() == ctx.push_constants {
+        return modules;
+    }
+    cfg_if::cfg_if! {
+        if #[cfg(hot_reload)] {
+            eprintln!("Rebuilding the shaders for the constants of the adapter");
+            shader::rebuild(options, ctx.push_constants);
+        } else {
+            let _ = options;
+        }
+    }
+    shader::precompiled(ctx.push_constants)
+}
+
+/// Creates a new device for `gfx` after it lost its old one, keeping everything that
+/// does not live on the device
+async fn recreate_graphics(gfx: Graphics) -> Result<Graphics, ContextError> {
+    let Graphics {
+        rpass,
+        ctx,
+        mut controller,
+        ui,
+        mut ui_state,
+        window,
+        options,
+    } = gfx;
+    let (modules, grid_backup) = rpass.into_recovery();
+    // The window can only have one surface at a time
+    drop(ctx);
+
+    let mut ctx = GraphicsContext::new(window.clone(), &options).await?;
+    let modules = modules_for(&ctx, &options, modules);
+    if !ui_state.vsync {
+        ctx.set_vsync(false);
+    }
+    if let Some(grid) = grid_backup {
+        controller.restore_grid(grid);
+    }
+    let rpass =
+        RenderPass::new(&ctx, modules, &controller).map_err(ContextError::CreatePipelines)?;
+    ui_state.gpu_times_supported = rpass.profiling_supported();
+    ui_state.device_info = Some(ctx.device_info.clone());
+
+    Ok(Graphics {
+        rpass,
+        ctx,
+        controller,
+        ui,
+        ui_state,
+        window,
+        options,
+    })
 }
@@ -0,0 +1,442 @@
+//! The automata the grid can run: the falling sand, and the Moore neighbourhood automata
+//! of `shared::automata`. Every automaton shares the grid, compute and render setup and
+//! brings its own `Rules`: its entry points, states, schedule of dispatches, CPU step
+//! and inspector.
+
+#[cfg(feature = "app")]
+use crate::inspector::Inspection;
+use crate::{cpu, shader::entry_points};
+use shared::automata::{self, Automaton};
+use shared::electrics;
+use shared::grid::Grid;
+use shared::particle::{Particle, ParticleType};
+use shared::tile;
+
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum AutomatonKind {
+    #[default]
+    Sand,
+    Life,
+    Wireworld,
+    BriansBrain,
+    LangtonsAnt,
+}
+
+/// A paintable state of an automaton
+#[derive(Clone, Copy, PartialEq)]
+pub struct State {
+    pub name: &'static str,
+    pub bits: u32,
+}
+
+const fn state(name: &'static str, bits: u32) -> State {
+    State { name, bits }
+}
+
+const SAND_STATES: &[State] = &[
+    state("Empty", ParticleType::Empty as u32),
+    state("Sand", ParticleType::Sand as u32),
+    state("Water", ParticleType::Water as u32),
+    state("Conductor", ParticleType::Conductor as u32),
+    state("Electron Head", ParticleType::ElectronHead as u32),
+    state("Electron Tail", ParticleType::ElectronTail as u32),
+];
+const LIFE_STATES: &[State] = &[state("Dead", 0), state("Alive", 1)];
+const WIREWORLD_STATES: &[State] = &[
+    state("Empty", 0),
+    state("Conductor", 3),
+    state("Electron Head", 1),
+    state("Electron Tail", 2),
+];
+const BRIANS_BRAIN_STATES: &[State] = &[state("Off", 0), state("On", 1), state("Dying", 2)];
+const LANGTONS_ANT_STATES: &[State] = &[state("White", 0), state("Black", 1), state("Ant", 2)];
+
+/// One part of a run of sub-steps, carried out the same way on the CPU and the GPU
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Dispatch {
+    /// One sub-step of the step entry point on the 2x2 blocks at `offset`, in place
+    Blocks { offset: u32 },
+    /// One sub-step of the step entry point on every cell at once, from the grid into
+    /// the scratch grid
+    Cells,
+    /// `tile::STEPS` sub-steps of the falling sand from workgroup memory, starting at
+    /// `offset`
+    Tiled { offset: u32 },
+    /// One step of the electronics of the falling sand, see `shared::electrics`
+    Electrics,
+}
+
+/// Everything that sets an automaton apart from the others
+pub trait Rules: Sync {
+    fn name(&self) -> &'static str;
+
+    /// Entry point running one sub-step
+    fn step_entry_point(&self) -> &'static str;
+
+    /// Entry point writing the display colour of every cell into the canvas
+    fn color_entry_point(&self) -> &'static str;
+
+    /// Entry point painting the brush stroke into the grid with the bits of a state
+    fn paint_entry_point(&self) -> &'static str {
+        entry_points::state_paint_cs
+    }
+
+    /// States the brush can paint, the first being the background that fills a cleared
+    /// grid
+    fn states(&self) -> &'static [State];
+
+    /// Name of the state of a cell with `bits`
+    fn state_name(&self, bits: u32) -> &'static str {
+        state_name(self.states(), bits)
+    }
+
+    /// State of the cells sprinkled over the background by randomizing the grid, for
+    /// the automata that start well from noise
+    fn seed_state(&self) -> Option<u32> {
+        None
+    }
+
+    /// A cell of the background
+    fn background(&self) -> Particle {
+        Particle::from_bits(self.states()[0].bits)
+    }
+
+    /// Whether `bits` decode to a cell of the automaton
+    fn valid(&self, _bits: u32) -> bool {
+        true
+    }
+
+    /// Whether the cells are `ParticleType` materials, which can be counted, isolated by
+    /// the debug render modes and stepped by the tiled shader
+    fn materials(&self) -> bool {
+        false
+    }
+
+    /// The dispatches running `sub_steps` sub-steps after the first `steps`, with the
+    /// tiled shader where `tiled` is set and the automaton has one
+    fn schedule(&self, _steps: u64, sub_steps: u32, _tiled: bool) -> Vec<Dispatch> {
+        vec![Dispatch::Cells; sub_steps as usize]
+    }
+
+    /// Runs one sub-step at `offset` on the CPU, the way the step entry point does
+    fn step_cpu(&self, grid: &mut Grid<Particle>, offset: u32);
+
+    /// Describes the inspected cell in the tooltip of the inspector
+    #[cfg(feature = "app")]
+    fn inspector_ui(&self, ui: &mut egui::Ui, inspection: &Inspection) {
+        let cell = inspection.cell;
+        egui::Grid::new("inspector_fields").show(ui, |ui| {
+            ui.label("Cell");
+            ui.label(format!("({}, {})", cell.x, cell.y));
+            ui.end_row();
+            ui.label("State");
+            ui.label(self.state_name(inspection.particle().to_bits()));
+            ui.end_row();
+        });
+    }
+}
+
+impl AutomatonKind {
+    pub const COUNT: usize = 5;
+
+    pub fn from_value(value: u32) -> Self {
+        match value {
+            0 => Self::Sand,
+            1 => Self::Life,
+            2 => Self::Wireworld,
+            3 => Self::BriansBrain,
+            4 => Self::LangtonsAnt,
+            _ => panic!("Invalid value"),
+        }
+    }
+
+    pub fn iter() -> impl Iterator<Item = Self> {
+        (0..Self::COUNT as u32).map(Self::from_value)
+    }
+
+    pub fn rules(self) -> &'static dyn Rules {
+        match self {
+            Self::Sand => &FallingSand,
+            Self::Life => &Life,
+            Self::Wireworld => &Wireworld,
+            Self::BriansBrain => &BriansBrain,
+            Self::LangtonsAnt => &LangtonsAnt,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        self.rules().name()
+    }
+
+    /// A grid of `w` by `h` cleared to the background, sprinkled with the seed state
+    /// when `randomize` is set
+    pub fn new_grid(self, w: usize, h: usize, randomize: bool) -> Grid<Particle> {
+        let rules = self.rules();
+        match rules.seed_state() {
+            Some(seed) if randomize => Grid::from_fn(w, h, |_, _| {
+                if rand::random::<f32>() < 0.25 {
+                    Particle::from_bits(seed)
+                } else {
+                    rules.background()
+                }
+            }),
+            _ => Grid::from_fn(w, h, |_, _| rules.background()),
+        }
+    }
+
+    /// Whether the bits of every cell of `grid` decode to a cell of the automaton
+    pub fn valid(self, grid: &Grid<Particle>) -> bool {
+        let rules = self.rules();
+        grid.buffer
+            .iter()
+            .all(|particle| rules.valid(particle.to_bits()))
+    }
+}
+
+fn state_name(states: &[State], bits: u32) -> &'static str {
+    states
+        .iter()
+        .find(|state| state.bits == bits)
+        .map_or("Unknown", |state| state.name)
+}
+
+/// The materials of the falling sand, stepped in 2x2 Margolus blocks at alternating
+/// offsets. The electronics are stepped every cell at once between runs of sub-steps,
+/// see `shared::electrics`.
+struct FallingSand;
+
+impl Rules for FallingSand {
+    fn name(&self) -> &'static str {
+        "Falling Sand"
+    }
+
+    fn step_entry_point(&self) -> &'static str {
+        entry_points::main_cs
+    }
+
+    fn color_entry_point(&self) -> &'static str {
+        entry_points::color_cs
+    }
+
+    fn paint_entry_point(&self) -> &'static str {
+        entry_points::paint_cs
+    }
+
+    fn states(&self) -> &'static [State] {
+        SAND_STATES
+    }
+
+    fn state_name(&self, bits: u32) -> &'static str {
+        ParticleType::from_value(Particle::from_bits(bits).behaviour()).name()
+    }
+
+    fn background(&self) -> Particle {
+        Particle::default()
+    }
+
+    fn valid(&self, bits: u32) -> bool {
+        (Particle::from_bits(bits).behaviour() as usize) < ParticleType::COUNT
+    }
+
+    fn materials(&self) -> bool {
+        true
+    }
+
+    fn schedule(&self, steps: u64, sub_steps: u32, tiled: bool) -> Vec<Dispatch> {
+        let mut schedule = Vec::new();
+        let (mut step, end) = (steps, steps + sub_steps as u64);
+        while step < end {
+            let offset = (step % 2) as u32;
+            let aligned = step % tile::STEPS as u64 == 0;
+            let dispatched = if tiled && aligned && end - step >= tile::STEPS as u64 {
+                schedule.push(Dispatch::Tiled { offset });
+                tile::STEPS
+            } else {
+                schedule.push(Dispatch::Blocks { offset });
+                1
+            };
+            step += dispatched as u64;
+            if step % electrics::RUN as u64 == 0 {
+                schedule.extend([Dispatch::Electrics; electrics::STEPS_PER_RUN as usize]);
+            }
+        }
+        schedule
+    }
+
+    fn step_cpu(&self, grid: &mut Grid<Particle>, offset: u32) {
+        cpu::step(grid, offset);
+    }
+
+    #[cfg(feature = "app")]
+    fn inspector_ui(&self, ui: &mut egui::Ui, inspection: &Inspection) {
+        let cell = inspection.cell;
+        let block = inspection.block();
+        egui::Grid::new("inspector_fields").show(ui, |ui| {
+            ui.label("Cell");
+            ui.label(format!("({}, {})", cell.x, cell.y));
+            ui.end_row();
+            for (name, value) in inspection.particle().fields() {
+                ui.label(name);
+                ui.label(value);
+                ui.end_row();
+            }
+            ui.label("Block");
+            ui.label(format!(
+                "({}, {}) offset {}",
+                block.x, block.y, inspection.offset
+            ));
+            ui.end_row();
+        });
+        ui.separator();
+        egui::Grid::new("inspector_block").show(ui, |ui| {
+            for y in 0..2 {
+                for x in 0..2 {
+                    let pos = block + glam::ivec2(x, y);
+                    let text = inspection
+                        .get(pos)
+                        .map_or("-", |particle| self.state_name(particle.to_bits()));
+                    if pos == cell.as_ivec2() {
+                        ui.strong(text);
+                    } else {
+                        ui.label(text);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+    }
+}
+
+struct Life;
+
+impl Rules for Life {
+    fn name(&self) -> &'static str {
+        "Game of Life"
+    }
+
+    fn step_entry_point(&self) -> &'static str {
+        entry_points::life_cs
+    }
+
+    fn color_entry_point(&self) -> &'static str {
+        entry_points::life_color_cs
+    }
+
+    fn states(&self) -> &'static [State] {
+        LIFE_STATES
+    }
+
+    fn state_name(&self, bits: u32) -> &'static str {
+        state_name(self.states(), automata::Life::from_bits(bits).to_bits())
+    }
+
+    fn seed_state(&self) -> Option<u32> {
+        Some(automata::Life::Alive.to_bits())
+    }
+
+    fn step_cpu(&self, grid: &mut Grid<Particle>, _offset: u32) {
+        cpu::step_automaton::<automata::Life>(grid);
+    }
+}
+
+struct Wireworld;
+
+impl Rules for Wireworld {
+    fn name(&self) -> &'static str {
+        "Wireworld"
+    }
+
+    fn step_entry_point(&self) -> &'static str {
+        entry_points::wireworld_cs
+    }
+
+    fn color_entry_point(&self) -> &'static str {
+        entry_points::wireworld_color_cs
+    }
+
+    fn states(&self) -> &'static [State] {
+        WIREWORLD_STATES
+    }
+
+    fn state_name(&self, bits: u32) -> &'static str {
+        state_name(
+            self.states(),
+            automata::Wireworld::from_bits(bits).to_bits(),
+        )
+    }
+
+    fn step_cpu(&self, grid: &mut Grid<Particle>, _offset: u32) {
+        cpu::step_automaton::<automata::Wireworld>(grid);
+    }
+}
+
+struct BriansBrain;
+
+impl Rules for BriansBrain {
+    fn name(&self) -> &'static str {
+        "Brian's Brain"
+    }
+
+    fn step_entry_point(&self) -> &'static str {
+        entry_points::brians_brain_cs
+    }
+
+    fn color_entry_point(&self) -> &'static str {
+        entry_points::brians_brain_color_cs
+    }
+
+    fn states(&self) -> &'static [State] {
+        BRIANS_BRAIN_STATES
+    }
+
+    fn state_name(&self, bits: u32) -> &'static str {
+        state_name(
+            self.states(),
+            automata::BriansBrain::from_bits(bits).to_bits(),
+        )
+    }
+
+    fn seed_state(&self) -> Option<u32> {
+        Some(automata::BriansBrain::On.to_bits())
+    }
+
+    fn step_cpu(&self, grid: &mut Grid<Particle>, _offset: u32) {
+        cpu::step_automaton::<automata::BriansBrain>(grid);
+    }
+}
+
+struct LangtonsAnt;
+
+impl Rules for LangtonsAnt {
+    fn name(&self) -> &'static str {
+        "Langton's Ant"
+    }
+
+    fn step_entry_point(&self) -> &'static str {
+        entry_points::langtons_ant_cs
+    }
+
+    fn color_entry_point(&self) -> &'static str {
+        entry_points::langtons_ant_color_cs
+    }
+
+    fn states(&self) -> &'static [State] {
+        LANGTONS_ANT_STATES
+    }
+
+    fn state_name(&self, bits: u32) -> &'static str {
+        let cell = automata::LangtonsAnt::from_bits(bits);
+        match (cell.ant(), cell.black()) {
+            (Some(0), _) => "Ant facing up",
+            (Some(1), _) => "Ant facing right",
+            (Some(2), _) => "Ant facing down",
+            (Some(_), _) => "Ant facing left",
+            (None, true) => "Black",
+            (None, false) => "White",
+        }
+    }
+
+    fn step_cpu(&self, grid: &mut Grid<Particle>, _offset: u32) {
+        cpu::step_automaton::<automata::LangtonsAnt>(grid);
+    }
+}
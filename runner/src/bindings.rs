@@ -0,0 +1,170 @@
+use egui_winit::winit::{
+    event::MouseButton,
+    keyboard::{Key, NamedKey},
+};
+use serde::{Deserialize, Serialize};
+use shared::particle::ParticleType;
+use std::collections::BTreeMap;
+
+const FILE_NAME: &str = "bindings.toml";
+
+/// Which of the two brush materials a mouse button paints with
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Slot {
+    Primary,
+    Secondary,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tool {
+    Brush,
+    Eraser,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Pause,
+    Step,
+    StepPair,
+    BrushGrow,
+    BrushShrink,
+    ZoomIn,
+    ZoomOut,
+    Tool(Tool),
+    Material(ParticleType),
+    SecondaryMaterial(ParticleType),
+}
+
+/// Key and mouse bindings, loaded from `bindings.toml` in the config directory.
+///
+/// Keys are either a single character or a named key such as `Space` or `F1`.
+/// A table present in the file replaces the default bindings of that table.
+/// ```toml
+/// [keys]
+/// Space = "pause"
+/// "1" = { material = "sand" }
+/// "!" = { secondary_material = "sand" }
+///
+/// [mouse]
+/// left = "primary"
+/// right = "secondary"
+/// ```
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bindings {
+    pub keys: BTreeMap<String, Action>,
+    pub mouse: BTreeMap<String, Slot>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let keys = [
+            ("Space", Action::Pause),
+            ("x", Action::Step),
+            ("c", Action::StepPair),
+            ("]", Action::BrushGrow),
+            ("[", Action::BrushShrink),
+            ("=", Action::ZoomIn),
+            ("-", Action::ZoomOut),
+            ("b", Action::Tool(Tool::Brush)),
+            ("e", Action::Tool(Tool::Eraser)),
+            ("0", Action::Material(ParticleType::Empty)),
+            ("1", Action::Material(ParticleType::Sand)),
+            ("2", Action::Material(ParticleType::Water)),
+            ("3", Action::Material(ParticleType::Conductor)),
+            ("4", Action::Material(ParticleType::ElectronHead)),
+            ("5", Action::Material(ParticleType::ElectronTail)),
+        ];
+        let mouse = [("left", Slot::Primary), ("right", Slot::Secondary)];
+        Self {
+            keys: keys.map(|(k, v)| (k.to_string(), v)).into(),
+            mouse: mouse.map(|(k, v)| (k.to_string(), v)).into(),
+        }
+    }
+}
+
+impl Bindings {
+    pub fn load() -> Self {
+        let bindings: Self = crate::config::load(FILE_NAME);
+        for key in bindings.keys.keys() {
+            if parse_key(key).is_none() {
+                eprintln!("Unknown key in {FILE_NAME}: {key:?}");
+            }
+        }
+        for button in bindings.mouse.keys() {
+            if parse_mouse_button(button).is_none() {
+                eprintln!("Unknown mouse button in {FILE_NAME}: {button:?}");
+            }
+        }
+        bindings
+    }
+
+    pub fn key_action(&self, key: &Key) -> Option<Action> {
+        let key = match key {
+            Key::Character(c) => Key::Character(c.to_lowercase().into()),
+            key => key.clone(),
+        };
+        self.keys
+            .iter()
+            .find(|(name, _)| parse_key(name).as_ref() == Some(&key))
+            .map(|(_, &action)| action)
+    }
+
+    pub fn mouse_slot(&self, button: MouseButton) -> Option<Slot> {
+        self.mouse
+            .iter()
+            .find(|(name, _)| parse_mouse_button(name) == Some(button))
+            .map(|(_, &slot)| slot)
+    }
+}
+
+fn parse_key(name: &str) -> Option<Key> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(Key::Character(c.to_lowercase().to_string().into()));
+    }
+    let named = match name {
+        "Space" => NamedKey::Space,
+        "Tab" => NamedKey::Tab,
+        "Enter" => NamedKey::Enter,
+        "Backspace" => NamedKey::Backspace,
+        "Delete" => NamedKey::Delete,
+        "Insert" => NamedKey::Insert,
+        "Home" => NamedKey::Home,
+        "End" => NamedKey::End,
+        "PageUp" => NamedKey::PageUp,
+        "PageDown" => NamedKey::PageDown,
+        "ArrowUp" => NamedKey::ArrowUp,
+        "ArrowDown" => NamedKey::ArrowDown,
+        "ArrowLeft" => NamedKey::ArrowLeft,
+        "ArrowRight" => NamedKey::ArrowRight,
+        "F1" => NamedKey::F1,
+        "F2" => NamedKey::F2,
+        "F3" => NamedKey::F3,
+        "F4" => NamedKey::F4,
+        "F5" => NamedKey::F5,
+        "F6" => NamedKey::F6,
+        "F7" => NamedKey::F7,
+        "F8" => NamedKey::F8,
+        "F9" => NamedKey::F9,
+        "F10" => NamedKey::F10,
+        "F11" => NamedKey::F11,
+        "F12" => NamedKey::F12,
+        _ => return None,
+    };
+    Some(Key::Named(named))
+}
+
+fn parse_mouse_button(name: &str) -> Option<MouseButton> {
+    Some(match name {
+        "left" => MouseButton::Left,
+        "right" => MouseButton::Right,
+        "middle" => MouseButton::Middle,
+        "back" => MouseButton::Back,
+        "forward" => MouseButton::Forward,
+        _ => return None,
+    })
+}
@@ -0,0 +1,242 @@
+use crate::{constants::ShaderConstants, context::GraphicsContext};
+use glam::*;
+use shared::push_constants::sandsim::BloomConstants;
+use wgpu::{BindGroup, BindGroupLayout, TextureView};
+
+/// Format of the offscreen texture the simulation is rendered to
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// Number of levels in the blur chain, the first one being half the surface size
+const LEVELS: u32 = 6;
+
+pub struct BloomPipelines {
+    pub prefilter: wgpu::RenderPipeline,
+    pub downsample: wgpu::RenderPipeline,
+    pub upsample: wgpu::RenderPipeline,
+    pub composite: wgpu::RenderPipeline,
+}
+
+/// Offscreen HDR target and the mip chain used to blur the light above 1.0 in it
+pub struct Bloom {
+    size: UVec2,
+    hdr_view: TextureView,
+    hdr_bind_group: BindGroup,
+    level_views: Vec<TextureView>,
+    level_bind_groups: Vec<BindGroup>,
+}
+
+impl Bloom {
+    pub fn new(ctx: &GraphicsContext, layout: &BindGroupLayout) -> Self {
+        let size = uvec2(ctx.config.width, ctx.config.height).max(UVec2::ONE);
+        let half = (size / 2).max(UVec2::ONE);
+        let levels = LEVELS.min(half.min_element().ilog2() + 1);
+
+        let create_texture = |label, size: UVec2, mip_level_count| {
+            ctx.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: HDR_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+        };
+        let hdr = create_texture("HDR Texture", size, 1);
+        let chain = create_texture("Bloom Texture", half, levels);
+
+        let sampler = ctx.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Bloom Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let create_bind_group = |view: &TextureView| {
+            ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Bloom Bind Group"),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&sampler),
+                    },
+                ],
+            })
+        };
+
+        let hdr_view = hdr.create_view(&wgpu::TextureViewDescriptor::default());
+        let hdr_bind_group = create_bind_group(&hdr_view);
+        let level_views: Vec<_> = (0..levels)
+            .map(|level| {
+                chain.create_view(&wgpu::TextureViewDescriptor {
+                    base_mip_level: level,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let level_bind_groups = level_views.iter().map(create_bind_group).collect();
+
+        Self {
+            size,
+            hdr_view,
+            hdr_bind_group,
+            level_views,
+            level_bind_groups,
+        }
+    }
+
+    /// Recreates the textures when the surface no longer matches them
+    pub fn resize(&mut self, ctx: &GraphicsContext, layout: &BindGroupLayout) {
+        if self.size != uvec2(ctx.config.width, ctx.config.height) {
+            *self = Self::new(ctx, layout);
+        }
+    }
+
+    pub fn hdr_view(&self) -> &TextureView {
+        &self.hdr_view
+    }
+
+    /// Number of draws `render` records at most
+    pub fn draws(&self) -> u32 {
+        2 * self.level_views.len() as u32
+    }
+
+    fn level_size(&self, level: usize) -> UVec2 {
+        (self.size / 2 >> level as u32).max(UVec2::ONE)
+    }
+
+    /// Blurs the HDR texture down and back up the chain, then composites the result
+    /// onto `output_view`. The chain is skipped entirely when `strength` is zero.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipelines: &BloomPipelines,
+        output_view: &TextureView,
+        strength: f32,
+        shader_constants: &ShaderConstants,
+    ) {
+        let levels = self.level_views.len();
+        let constants = |source: UVec2, target: UVec2| BloomConstants {
+            texel_size: 1.0 / source.as_vec2(),
+            pixel_size: 1.0 / target.as_vec2(),
+            strength,
+        };
+        if strength > 0.0 {
+            draw(
+                encoder,
+                &pipelines.prefilter,
+                &self.level_views[0],
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                &[&self.hdr_bind_group],
+                constants(self.size, self.level_size(0)),
+                shader_constants,
+            );
+            for level in 1..levels {
+                draw(
+                    encoder,
+                    &pipelines.downsample,
+                    &self.level_views[level],
+                    wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    &[&self.level_bind_groups[level - 1]],
+                    constants(self.level_size(level - 1), self.level_size(level)),
+                    shader_constants,
+                );
+            }
+            for level in (1..levels).rev() {
+                draw(
+                    encoder,
+                    &pipelines.upsample,
+                    &self.level_views[level - 1],
+                    wgpu::LoadOp::Load,
+                    &[&self.level_bind_groups[level]],
+                    constants(self.level_size(level), self.level_size(level - 1)),
+                    shader_constants,
+                );
+            }
+        }
+        draw(
+            encoder,
+            &pipelines.composite,
+            output_view,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            &[&self.hdr_bind_group, &self.level_bind_groups[0]],
+            constants(self.level_size(0), self.size),
+            shader_constants,
+        );
+    }
+}
+
+/// Draws a fullscreen triangle into `target`
+pub fn draw(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::RenderPipeline,
+    target: &TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+    bind_groups: &[&BindGroup],
+    constants: BloomConstants,
+    shader_constants: &ShaderConstants,
+) {
+    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Bloom Render Pass"),
+        occlusion_query_set: None,
+        timestamp_writes: None,
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+    });
+    rpass.set_pipeline(pipeline);
+    shader_constants.set_render(
+        &mut rpass,
+        wgpu::ShaderStages::FRAGMENT,
+        bind_groups.len() as u32,
+        bytemuck::bytes_of(&constants),
+    );
+    for (i, bind_group) in bind_groups.iter().enumerate() {
+        rpass.set_bind_group(i as u32, *bind_group, &[]);
+    }
+    rpass.draw(0..3, 0..1);
+}
+
+/// Layout of a sampled texture at binding 0 and its sampler at binding 1
+pub fn create_sampled_bind_group_layout(ctx: &GraphicsContext) -> BindGroupLayout {
+    ctx.device
+        .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sampled Texture Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        })
+}
@@ -0,0 +1,169 @@
+use crate::{bloom, constants::ShaderConstants, context::GraphicsContext};
+use glam::*;
+use shared::push_constants::sandsim::BloomConstants;
+use wgpu::{BindGroup, BindGroupLayout, TextureView};
+
+/// One texel per cell holding its final colour, written by the colour compute pass and
+/// sampled by the fragment shader. Lower mip levels are only generated while zoomed out.
+pub struct Canvas {
+    size: UVec2,
+    storage_bind_group: BindGroup,
+    nearest_bind_group: BindGroup,
+    linear_bind_group: BindGroup,
+    level_views: Vec<TextureView>,
+    level_bind_groups: Vec<BindGroup>,
+}
+
+impl Canvas {
+    pub fn new(
+        ctx: &GraphicsContext,
+        size: UVec2,
+        storage_layout: &BindGroupLayout,
+        sampled_layout: &BindGroupLayout,
+    ) -> Self {
+        let levels = size.max_element().ilog2() + 1;
+        let texture = ctx.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Canvas Texture"),
+            size: wgpu::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: bloom::HDR_FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let level_views: Vec<_> = (0..levels)
+            .map(|level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    base_mip_level: level,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let storage_bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Canvas Storage Bind Group"),
+            layout: storage_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&level_views[0]),
+            }],
+        });
+
+        let create_sampler = |mag_filter| {
+            ctx.device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("Canvas Sampler"),
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                mag_filter,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            })
+        };
+        let create_bind_group = |view: &TextureView, sampler: &wgpu::Sampler| {
+            ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Canvas Bind Group"),
+                layout: sampled_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                ],
+            })
+        };
+        let nearest = create_sampler(wgpu::FilterMode::Nearest);
+        let linear = create_sampler(wgpu::FilterMode::Linear);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let level_bind_groups = level_views
+            .iter()
+            .map(|view| create_bind_group(view, &linear))
+            .collect();
+
+        Self {
+            size,
+            storage_bind_group,
+            nearest_bind_group: create_bind_group(&view, &nearest),
+            linear_bind_group: create_bind_group(&view, &linear),
+            level_views,
+            level_bind_groups,
+        }
+    }
+
+    pub fn storage_bind_group(&self) -> &BindGroup {
+        &self.storage_bind_group
+    }
+
+    /// Bind group sampling the whole mip chain, magnified with nearest or linear filtering
+    pub fn bind_group(&self, linear: bool) -> &BindGroup {
+        if linear {
+            &self.linear_bind_group
+        } else {
+            &self.nearest_bind_group
+        }
+    }
+
+    /// Number of mip levels, including the full size one
+    pub fn levels(&self) -> u32 {
+        self.level_views.len() as u32
+    }
+
+    fn level_size(&self, level: usize) -> UVec2 {
+        (self.size >> level as u32).max(UVec2::ONE)
+    }
+
+    /// Downsamples each mip level from the one above it
+    pub fn generate_mips(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        downsample: &wgpu::RenderPipeline,
+        shader_constants: &ShaderConstants,
+    ) {
+        for level in 1..self.level_views.len() {
+            let source = self.level_size(level - 1);
+            let target = self.level_size(level);
+            bloom::draw(
+                encoder,
+                downsample,
+                &self.level_views[level],
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                &[&self.level_bind_groups[level - 1]],
+                BloomConstants {
+                    texel_size: 1.0 / source.as_vec2(),
+                    pixel_size: 1.0 / target.as_vec2(),
+                    strength: 0.0,
+                },
+                shader_constants,
+            );
+        }
+    }
+}
+
+pub fn create_storage_bind_group_layout(ctx: &GraphicsContext) -> BindGroupLayout {
+    ctx.device
+        .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Canvas Storage Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: bloom::HDR_FORMAT,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            }],
+        })
+}
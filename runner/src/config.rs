@@ -0,0 +1,44 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::path::PathBuf;
+
+/// Path of `file` inside the user's sandsim config directory
+pub fn path(file: &str) -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("sandsim").join(file))
+}
+
+/// Loads `file` from the config directory, falling back to the default when it is
+/// missing or invalid
+pub fn load<T: DeserializeOwned + Default>(file: &str) -> T {
+    let Some(path) = path(file) else {
+        return T::default();
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => toml::from_str(&contents).unwrap_or_else(|err| {
+            eprintln!("Invalid config file {}: {err}", path.display());
+            T::default()
+        }),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => T::default(),
+        Err(err) => {
+            eprintln!("Failed to read config file {}: {err}", path.display());
+            T::default()
+        }
+    }
+}
+
+/// Writes `value` to `file` in the config directory, creating the directory if needed
+pub fn save<T: Serialize>(file: &str, value: &T) {
+    let Some(path) = path(file) else {
+        return;
+    };
+    let result = toml::to_string_pretty(value)
+        .map_err(|err| err.to_string())
+        .and_then(|contents| {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+            }
+            std::fs::write(&path, contents).map_err(|err| err.to_string())
+        });
+    if let Err(err) = result {
+        eprintln!("Failed to write config file {}: {err}", path.display());
+    }
+}
@@ -0,0 +1,174 @@
+use std::cell::RefCell;
+use wgpu::{BindGroup, BindGroupLayout};
+
+/// Size of the largest constants struct, which is also the push constant limit requested
+pub const MAX_SIZE: u64 = 128;
+/// Slots allocated up front, more are reserved as needed
+const MIN_CAPACITY: u32 = 64;
+
+/// Sets the constants of draws and dispatches. These are push constants where the
+/// adapter supports them. Otherwise every draw or dispatch gets its own slot of a
+/// uniform buffer, bound by dynamic offset after the other bind groups of the pipeline,
+/// which the shaders expect with the `constant-buffer` feature. Unlike a storage buffer,
+/// it is also available to the fragment shader on GL.
+pub struct ShaderConstants {
+    buffer: Option<ConstantBuffer>,
+}
+
+struct ConstantBuffer {
+    layout: BindGroupLayout,
+    buffer: wgpu::Buffer,
+    bind_group: BindGroup,
+    /// Distance between slots, a multiple of the offset alignment
+    stride: u64,
+    capacity: u32,
+    /// Constants set since the last upload, one slot each
+    staged: RefCell<Vec<u8>>,
+}
+
+impl ShaderConstants {
+    /// `push_constants` is how the shaders were compiled to take their constants
+    pub fn new(device: &wgpu::Device, push_constants: bool) -> Self {
+        if push_constants {
+            return Self { buffer: None };
+        }
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Constants Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(MAX_SIZE),
+                },
+                count: None,
+            }],
+        });
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let stride = MAX_SIZE.next_multiple_of(alignment);
+        let (buffer, bind_group) = create_buffer(device, &layout, stride, MIN_CAPACITY);
+        Self {
+            buffer: Some(ConstantBuffer {
+                layout,
+                buffer,
+                bind_group,
+                stride,
+                capacity: MIN_CAPACITY,
+                staged: RefCell::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Layout to append to the bind group layouts of every pipeline, if any
+    pub fn bind_group_layout(&self) -> Option<&BindGroupLayout> {
+        self.buffer.as_ref().map(|buffer| &buffer.layout)
+    }
+
+    pub fn push_constant_ranges(
+        &self,
+        stages: wgpu::ShaderStages,
+        size: usize,
+    ) -> Vec<wgpu::PushConstantRange> {
+        match self.buffer {
+            Some(_) => Vec::new(),
+            None => vec![wgpu::PushConstantRange {
+                stages,
+                range: 0..size as u32,
+            }],
+        }
+    }
+
+    /// Makes room for the `count` draws or dispatches recorded before the next upload.
+    /// Must be called before recording them, since growing replaces the bind group.
+    pub fn reserve(&mut self, device: &wgpu::Device, count: u32) {
+        let Some(buffer) = &mut self.buffer else {
+            return;
+        };
+        if count > buffer.capacity {
+            buffer.capacity = count.next_power_of_two();
+            (buffer.buffer, buffer.bind_group) =
+                create_buffer(device, &buffer.layout, buffer.stride, buffer.capacity);
+        }
+    }
+
+    /// Stages `data` in the next slot and returns its offset, which lies past the buffer
+    /// unless enough slots were reserved
+    fn stage(buffer: &ConstantBuffer, data: &[u8]) -> u32 {
+        let mut staged = buffer.staged.borrow_mut();
+        let offset = staged.len();
+        staged.extend_from_slice(data);
+        staged.resize(offset + buffer.stride as usize, 0);
+        offset as u32
+    }
+
+    /// Sets the constants of the following dispatches. `index` is the number of other bind
+    /// groups of the pipeline.
+    pub fn set_compute(&self, cpass: &mut wgpu::ComputePass, index: u32, data: &[u8]) {
+        match &self.buffer {
+            Some(buffer) => {
+                let offset = Self::stage(buffer, data);
+                cpass.set_bind_group(index, &buffer.bind_group, &[offset]);
+            }
+            None => cpass.set_push_constants(0, data),
+        }
+    }
+
+    /// Sets the constants of the following draws, see `set_compute`
+    #[cfg(feature = "app")]
+    pub fn set_render(
+        &self,
+        rpass: &mut wgpu::RenderPass,
+        stages: wgpu::ShaderStages,
+        index: u32,
+        data: &[u8],
+    ) {
+        match &self.buffer {
+            Some(buffer) => {
+                let offset = Self::stage(buffer, data);
+                rpass.set_bind_group(index, &buffer.bind_group, &[offset]);
+            }
+            None => rpass.set_push_constants(stages, 0, data),
+        }
+    }
+
+    /// Writes the staged constants to the buffer. Must be called before submitting the
+    /// commands that set them, after which their slots are reused.
+    pub fn upload(&self, queue: &wgpu::Queue) {
+        let Some(buffer) = &self.buffer else {
+            return;
+        };
+        let mut staged = buffer.staged.borrow_mut();
+        if !staged.is_empty() {
+            queue.write_buffer(&buffer.buffer, 0, &staged);
+            staged.clear();
+        }
+    }
+}
+
+fn create_buffer(
+    device: &wgpu::Device,
+    layout: &BindGroupLayout,
+    stride: u64,
+    capacity: u32,
+) -> (wgpu::Buffer, BindGroup) {
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Constants Buffer"),
+        size: stride * capacity as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Constants Bind Group"),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer: &buffer,
+                offset: 0,
+                size: wgpu::BufferSize::new(MAX_SIZE),
+            }),
+        }],
+    });
+    (buffer, bind_group)
+}
@@ -1,46 +1,322 @@
 use crate::Options;
 use egui_winit::winit::{dpi::PhysicalSize, window::Window};
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
 pub struct GraphicsContext {
     pub surface: wgpu::Surface<'static>,
-    pub device: wgpu::Device,
-    pub queue: wgpu::Queue,
+    /// Shared with the simulation
+    pub device: Arc<wgpu::Device>,
+    pub queue: Arc<wgpu::Queue>,
     pub config: wgpu::SurfaceConfiguration,
+    /// Whether the shaders take their constants as push constants, see `ShaderConstants`.
+    /// Decided from the adapter, the shader variant follows it.
+    pub push_constants: bool,
+    /// Whether shader modules are passed to the driver as is, rather than translated
+    /// by wgpu. Only some backends support this.
+    pub spirv_passthrough: bool,
+    pub device_info: DeviceInfo,
+    /// Set by the device lost callback
+    lost: Arc<AtomicBool>,
+}
+
+/// What the device was created from, shown in the Settings menu
+#[derive(Clone)]
+pub struct DeviceInfo {
+    adapter: wgpu::AdapterInfo,
+    /// Limits of the adapter, the device only asks for `required_limits`
+    limits: wgpu::Limits,
+    push_constants: bool,
+    spirv_passthrough: bool,
+    timestamp_queries: bool,
+}
+
+impl DeviceInfo {
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        let yes_no = |enabled| if enabled { "Yes" } else { "No" }.to_string();
+        let adapter = &self.adapter;
+        let limits = &self.limits;
+        let rows = [
+            ("Adapter", adapter.name.clone()),
+            ("Backend", format!("{:?}", adapter.backend)),
+            ("Type", format!("{:?}", adapter.device_type)),
+            (
+                "Driver",
+                format!("{} {}", adapter.driver, adapter.driver_info),
+            ),
+            ("Push constants", yes_no(self.push_constants)),
+            ("SPIR-V passthrough", yes_no(self.spirv_passthrough)),
+            ("Timestamp queries", yes_no(self.timestamp_queries)),
+        ];
+        let limit_rows = [
+            ("Texture size", limits.max_texture_dimension_2d as u64),
+            ("Buffer size", limits.max_buffer_size),
+            (
+                "Storage binding size",
+                limits.max_storage_buffer_binding_size as u64,
+            ),
+            (
+                "Workgroup invocations",
+                limits.max_compute_invocations_per_workgroup as u64,
+            ),
+            (
+                "Workgroup storage",
+                limits.max_compute_workgroup_storage_size as u64,
+            ),
+            (
+                "Workgroups per dimension",
+                limits.max_compute_workgroups_per_dimension as u64,
+            ),
+            ("Push constant size", limits.max_push_constant_size as u64),
+        ];
+        egui::Grid::new("device_info")
+            .num_columns(2)
+            .show(ui, |ui| {
+                for (name, value) in rows {
+                    ui.label(name);
+                    ui.label(value);
+                    ui.end_row();
+                }
+            });
+        ui.separator();
+        ui.label("Adapter limits");
+        egui::Grid::new("device_limits")
+            .num_columns(2)
+            .show(ui, |ui| {
+                for (name, value) in limit_rows {
+                    ui.label(name);
+                    ui.label(value.to_string());
+                    ui.end_row();
+                }
+            });
+    }
+}
+
+/// Why the window could not get a device to render with
+#[derive(Debug)]
+pub enum ContextError {
+    CreateSurface(wgpu::CreateSurfaceError),
+    /// No adapter can render to the window. Holds the adapters that were found, if any.
+    NoAdapter(Vec<wgpu::AdapterInfo>),
+    /// No adapter picked by `--adapter` can render to the window
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    AdapterNotFound(String, Vec<wgpu::AdapterInfo>),
+    RequestDevice(String, wgpu::RequestDeviceError),
+    /// The surface reports no formats or present modes for the named adapter
+    UnsupportedSurface(String),
+    /// The shaders lack an entry point the pipelines need
+    CreatePipelines(String),
+}
+
+impl std::fmt::Display for ContextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CreateSurface(err) => {
+                write!(f, "Failed to create a surface for the window: {err}")
+            }
+            Self::NoAdapter(adapters) => {
+                write!(f, "No adapter can render to the window")?;
+                write_tried(f, adapters)?;
+                write!(
+                    f,
+                    "\n--backend and --adapter select other backends and adapters"
+                )
+            }
+            Self::AdapterNotFound(adapter, adapters) => {
+                write!(
+                    f,
+                    "No adapter matching {adapter:?} can render to the window"
+                )?;
+                write_tried(f, adapters)
+            }
+            Self::RequestDevice(adapter, err) => {
+                write!(f, "Failed to create a device on {adapter}: {err}")
+            }
+            Self::UnsupportedSurface(adapter) => write!(
+                f,
+                "{adapter} has no formats or present modes for the window surface"
+            ),
+            Self::CreatePipelines(err) => write!(f, "Failed to create the pipelines: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ContextError {}
+
+fn write_tried(
+    f: &mut std::fmt::Formatter<'_>,
+    adapters: &[wgpu::AdapterInfo],
+) -> std::fmt::Result {
+    if adapters.is_empty() {
+        return write!(f, ", none were found");
+    }
+    write!(f, ". Tried:")?;
+    for (i, info) in adapters.iter().enumerate() {
+        write!(f, "\n    {i}: {}", describe(info))?;
+    }
+    Ok(())
+}
+
+fn describe(info: &wgpu::AdapterInfo) -> String {
+    let software = if info.device_type == wgpu::DeviceType::Cpu {
+        ", software"
+    } else {
+        ""
+    };
+    format!(
+        "{} ({:?}, {:?}{software})",
+        info.name, info.backend, info.device_type
+    )
+}
+
+/// Backends from `--backend`, then `WGPU_BACKEND`, then the defaults
+fn create_instance(options: &Options) -> wgpu::Instance {
+    let default_backends = if cfg!(target_arch = "wasm32") {
+        wgpu::Backends::BROWSER_WEBGPU
+    } else {
+        wgpu::Backends::VULKAN | wgpu::Backends::METAL
+    };
+    let backends = options
+        .backend
+        .or_else(wgpu::util::backend_bits_from_env)
+        .unwrap_or(default_backends);
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        dx12_shader_compiler: wgpu::util::dx12_shader_compiler_from_env().unwrap_or_default(),
+        flags: wgpu::InstanceFlags::default(),
+        gles_minor_version: wgpu::Gles3MinorVersion::Automatic,
+    })
+}
+
+/// Limits the device asks for: the downlevel defaults, which GL and older devices meet,
+/// with the texture size of the `adapter` limits
+pub fn required_limits(adapter: wgpu::Limits, push_constants: bool) -> wgpu::Limits {
+    wgpu::Limits {
+        max_push_constant_size: if push_constants {
+            crate::constants::MAX_SIZE as u32
+        } else {
+            0
+        },
+        ..wgpu::Limits::downlevel_defaults().using_resolution(adapter)
+    }
+}
+
+fn supports_push_constants(adapter: &wgpu::Adapter) -> bool {
+    adapter.features().contains(wgpu::Features::PUSH_CONSTANTS)
+        && adapter.limits().max_push_constant_size as u64 >= crate::constants::MAX_SIZE
+}
+
+/// Picks the adapter named by `--adapter`, or else the one wgpu prefers, which also
+/// honours `WGPU_ADAPTER_NAME` and `WGPU_POWER_PREF`. With a `surface`, only adapters
+/// that can render to it are considered.
+async fn select_adapter(
+    instance: &wgpu::Instance,
+    options: &Options,
+    surface: Option<&wgpu::Surface<'_>>,
+) -> Result<wgpu::Adapter, ContextError> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(name) = &options.adapter {
+        let adapters = instance.enumerate_adapters(wgpu::Backends::all());
+        let matches = |i: usize, info: &wgpu::AdapterInfo| match name.parse::<usize>() {
+            Ok(index) => i == index,
+            Err(_) => info.name.to_lowercase().contains(&name.to_lowercase()),
+        };
+        let infos: Vec<_> = adapters.iter().map(wgpu::Adapter::get_info).collect();
+        return adapters
+            .into_iter()
+            .enumerate()
+            .find(|(i, adapter)| {
+                matches(*i, &infos[*i])
+                    && surface.is_none_or(|surface| adapter.is_surface_supported(surface))
+            })
+            .map(|(_, adapter)| adapter)
+            .ok_or_else(|| ContextError::AdapterNotFound(name.clone(), infos));
+    }
+    #[cfg(target_arch = "wasm32")]
+    let _ = options;
+    wgpu::util::initialize_adapter_from_env_or_default(instance, surface)
+        .await
+        .ok_or_else(|| ContextError::NoAdapter(adapter_infos(instance)))
+}
+
+/// Whether the adapter picked by `GraphicsContext::new` is likely to support push
+/// constants. Checked before the window exists, so that hot reloading can start building
+/// the likely shader variant. The shaders are rebuilt if the guess was wrong.
+#[cfg(hot_reload)]
+pub fn probe_push_constants(options: &Options) -> bool {
+    let instance = create_instance(options);
+    futures::executor::block_on(select_adapter(&instance, options, None))
+        .is_ok_and(|adapter| supports_push_constants(&adapter))
+}
+
+/// Prints the adapters of the selected backends, numbered for `--adapter`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn list_adapters(options: &Options) {
+    let infos = adapter_infos(&create_instance(options));
+    if infos.is_empty() {
+        println!("No adapters found");
+    }
+    for (i, info) in infos.iter().enumerate() {
+        println!("{i}: {}", describe(info));
+        println!("    Driver: {} {}", info.driver, info.driver_info);
+    }
+}
+
+/// Adapters of the instance's backends, listed when none of them can be used. The browser
+/// does not enumerate its adapters.
+fn adapter_infos(instance: &wgpu::Instance) -> Vec<wgpu::AdapterInfo> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        instance
+            .enumerate_adapters(wgpu::Backends::all())
+            .iter()
+            .map(wgpu::Adapter::get_info)
+            .collect()
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = instance;
+        Vec::new()
+    }
 }
 
 impl GraphicsContext {
-    pub async fn new(window: Arc<Window>, options: &Options) -> GraphicsContext {
-        let backends = wgpu::util::backend_bits_from_env()
-            .unwrap_or(wgpu::Backends::VULKAN | wgpu::Backends::METAL);
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends,
-            dx12_shader_compiler: wgpu::util::dx12_shader_compiler_from_env().unwrap_or_default(),
-            flags: wgpu::InstanceFlags::default(),
-            gles_minor_version: wgpu::Gles3MinorVersion::Automatic,
-        });
+    /// Uses push constants where the adapter supports them, unless `--no-push-constants`
+    /// says otherwise. The shaders have to be in the matching variant.
+    pub async fn new(
+        window: Arc<Window>,
+        options: &Options,
+    ) -> Result<GraphicsContext, ContextError> {
+        let instance = create_instance(options);
 
         let inner_size = window.inner_size();
         let initial_surface = instance
             .create_surface(window)
-            .expect("Failed to create surface from window");
+            .map_err(ContextError::CreateSurface)?;
 
-        let adapter = wgpu::util::initialize_adapter_from_env_or_default(
-            &instance,
-            // Request an adapter which can render to our surface
-            Some(&initial_surface),
-        )
-        .await
-        .expect("Failed to find an appropriate adapter");
+        // Request an adapter which can render to our surface
+        let adapter = select_adapter(&instance, options, Some(&initial_surface)).await?;
+        let adapter_info = adapter.get_info();
+        let adapter_limits = adapter.limits();
 
-        let mut features = wgpu::Features::PUSH_CONSTANTS;
-        if !options.validate_spirv {
+        let push_constants = !options.no_push_constants && supports_push_constants(&adapter);
+        let mut features = wgpu::Features::empty();
+        if push_constants {
+            features |= wgpu::Features::PUSH_CONSTANTS;
+        }
+        // Otherwise wgpu translates the shaders, as with `--validate-spirv`
+        let spirv_passthrough = !options.validate_spirv
+            && adapter
+                .features()
+                .contains(wgpu::Features::SPIRV_SHADER_PASSTHROUGH);
+        if spirv_passthrough {
             features |= wgpu::Features::SPIRV_SHADER_PASSTHROUGH;
         }
-        let limits = wgpu::Limits {
-            max_push_constant_size: 128,
-            ..Default::default()
-        };
+        // Optional, only used to time the passes
+        features |= adapter.features() & wgpu::Features::TIMESTAMP_QUERY;
+        let limits = required_limits(adapter_limits.clone(), push_constants);
 
         // Create the logical device and command queue
         let (device, queue) = adapter
@@ -54,36 +330,61 @@ impl GraphicsContext {
                 None,
             )
             .await
-            .expect("Failed to create device");
+            .map_err(|err| ContextError::RequestDevice(describe(&adapter_info), err))?;
+
+        let lost = Arc::new(AtomicBool::new(false));
+        device.set_device_lost_callback({
+            let lost = lost.clone();
+            move |reason, message| {
+                // Dropping the device also counts as losing it
+                if !matches!(
+                    reason,
+                    wgpu::DeviceLostReason::Dropped | wgpu::DeviceLostReason::ReplacedCallback
+                ) {
+                    eprintln!("Device lost ({reason:?}): {message}");
+                    lost.store(true, Ordering::Relaxed);
+                }
+            }
+        });
 
         fn auto_configure_surface<'a>(
             adapter: &wgpu::Adapter,
             device: &wgpu::Device,
             surface: wgpu::Surface<'a>,
             size: PhysicalSize<u32>,
-        ) -> (wgpu::Surface<'a>, wgpu::SurfaceConfiguration) {
+        ) -> Result<(wgpu::Surface<'a>, wgpu::SurfaceConfiguration), ContextError> {
             let mut surface_config = surface
                 .get_default_config(adapter, size.width, size.height)
-                .unwrap_or_else(|| {
-                    panic!(
-                        "Missing formats/present modes in surface capabilities: {:#?}",
-                        surface.get_capabilities(adapter)
-                    )
-                });
+                .ok_or_else(|| ContextError::UnsupportedSurface(describe(&adapter.get_info())))?;
             surface_config.present_mode = wgpu::PresentMode::AutoVsync;
             surface.configure(device, &surface_config);
-            (surface, surface_config)
+            Ok((surface, surface_config))
         }
 
         let (surface, config) =
-            auto_configure_surface(&adapter, &device, initial_surface, inner_size);
+            auto_configure_surface(&adapter, &device, initial_surface, inner_size)?;
 
-        GraphicsContext {
+        Ok(GraphicsContext {
             surface,
-            device,
-            queue,
+            device: Arc::new(device),
+            queue: Arc::new(queue),
             config,
-        }
+            push_constants,
+            spirv_passthrough,
+            device_info: DeviceInfo {
+                adapter: adapter_info,
+                limits: adapter_limits,
+                push_constants,
+                spirv_passthrough,
+                timestamp_queries: features.contains(wgpu::Features::TIMESTAMP_QUERY),
+            },
+            lost,
+        })
+    }
+
+    /// Whether the device was lost, after which everything on it has to be recreated
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Relaxed)
     }
 
     pub fn set_vsync(&mut self, enable: bool) {
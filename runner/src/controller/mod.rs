@@ -1,5 +1,9 @@
 use crate::{
-    bind_group_buffer::{BindGroupBufferType, BufferData, SSBO},
+    automaton::AutomatonKind,
+    bindings::{Action, Bindings, Slot, Tool},
+    inspector::Inspection,
+    settings::ControllerSettings,
+    statistics::Statistics,
     user_event::UserEvent,
     Options,
 };
@@ -9,78 +13,249 @@ use egui_winit::winit::{
     dpi::{PhysicalPosition, PhysicalSize},
     event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta},
     event_loop::EventLoopProxy,
-    keyboard::Key,
 };
 use glam::*;
 use shared::grid::*;
-use shared::{particle::*, push_constants::sandsim::*};
-use shared::{UI_MENU_HEIGHT, UI_SIDEBAR_WIDTH};
-use std::time::Instant;
+use shared::{particle::*, push_constants::sandsim::*, tile};
+use web_time::Instant;
+
+/// Queued steps beyond this are spread over the following frames
+const MAX_PENDING_STEPS_PER_FRAME: u32 = 200;
+/// Roughly the end of the speed slider
+pub const MAX_SPEED: f32 = 99.0;
+/// Below zoom 1 a cell can be smaller than a pixel, such as for worlds larger than the
+/// window, and the canvas is sampled from its mip chain
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 100.0;
+
+/// States painted by the primary and secondary slots of the brush, see `Rules::states`
+#[derive(Clone, Copy)]
+struct Palette {
+    primary: u32,
+    secondary: u32,
+}
+
+impl Palette {
+    /// The first state after the background, and the background
+    fn new(automaton: AutomatonKind) -> Self {
+        let states = automaton.rules().states();
+        Self {
+            primary: states[1].bits,
+            secondary: states[0].bits,
+        }
+    }
+}
+
+/// Replacement of the whole grid requested from the UI
+#[derive(Clone, Copy, PartialEq)]
+pub enum GridReset {
+    Clear,
+    /// Sprinkles the seed state of the automaton over the background
+    Randomize,
+}
 
 pub struct Controller {
+    /// Size of the grid in cells
     size: PhysicalSize<u32>,
+    /// Size of the grid from the options, instead of following the viewport
+    world_size: Option<PhysicalSize<u32>>,
     start: Instant,
     fragment_constants: FragmentConstants,
-    compute_constants: ComputeConstants,
+    /// Contents the simulation is created with
     grid: Grid<Particle>,
+    viewport_offset: Vec2,
+    viewport_size: Vec2,
     cursor: Vec2,
     prev_cursor: Vec2,
-    cursor_down: bool,
-    cursor_right_down: bool,
-    current_particle_type: ParticleType,
+    pressed: Option<(MouseButton, Slot)>,
+    bindings: Bindings,
+    automaton: AutomatonKind,
+    /// Painted states of every automaton, indexed by `AutomatonKind`. Those of the
+    /// falling sand are `ParticleType`s.
+    palettes: [Palette; AutomatonKind::COUNT],
+    reset: Option<GridReset>,
+    tool: Tool,
     brush_size: f32,
     offset: u32,
     speed: f32,
     distance: f32,
+    paused: bool,
+    pending_steps: u32,
+    run_steps: u32,
+    step: u64,
     last_frame: Instant,
     zoom: f32,
+    /// Size of a cell in physical pixels at zoom 1
+    cell_size: f32,
+    linear_filtering: bool,
+    bloom_strength: f32,
+    scale_factor: f32,
     debug: bool,
+    render_mode: RenderMode,
+    isolated_material: ParticleType,
+    /// Use the tiled compute shader for runs of `tile::STEPS` sub-steps
+    tiled: bool,
+    validate_tiled: bool,
+    validation: Option<String>,
+    inspection: Option<Inspection>,
+    statistics: Statistics,
 }
 
 impl Controller {
-    pub fn new(size: PhysicalSize<u32>, options: &Options) -> Self {
+    pub fn new(
+        window_size: PhysicalSize<u32>,
+        options: &Options,
+        settings: &ControllerSettings,
+    ) -> Self {
         let now = Instant::now();
-        let size = PhysicalSize {
-            width: size.width - UI_SIDEBAR_WIDTH,
-            height: size.height - UI_MENU_HEIGHT,
-        };
-        let grid = Grid::<Particle>::from_fn(size.width as usize, size.height as usize, |_, _| {
-            Particle::default()
-        });
+        let cell_size = settings.cell_size.clamp(1.0, 16.0);
+        let world_size = options.world_size();
+        // The ui has not taken its share of the window yet
+        let viewport_size = uvec2(window_size.width, window_size.height).as_vec2();
+        let size = world_size.unwrap_or_else(|| grid_size_for(viewport_size, cell_size));
+        let mut grid = new_grid(AutomatonKind::Sand, size);
+        if let Some(scene) = &options.scene {
+            scene.place(&mut grid);
+        }
 
-        let debug = options.debug;
-        let speed = normalize_speed_down(!debug as u32 as f32);
+        let (debug, speed) = if options.debug {
+            (true, 0.0)
+        } else {
+            (settings.debug, options.speed.unwrap_or(settings.speed))
+        };
+        let mut palettes =
+            std::array::from_fn(|i| Palette::new(AutomatonKind::from_value(i as u32)));
+        palettes[AutomatonKind::Sand as usize] = Palette {
+            primary: options.material.unwrap_or(settings.primary_material) as u32,
+            secondary: settings.secondary_material as u32,
+        };
 
         Self {
             size,
+            world_size,
             start: now,
             fragment_constants: FragmentConstants::zeroed(),
-            compute_constants: ComputeConstants::zeroed(),
             grid,
+            viewport_offset: Vec2::ZERO,
+            viewport_size,
             cursor: Vec2::ZERO,
             prev_cursor: Vec2::ZERO,
-            cursor_down: false,
-            cursor_right_down: false,
-            current_particle_type: ParticleType::Sand,
-            brush_size: 20.0,
+            pressed: None,
+            bindings: Bindings::load(),
+            automaton: AutomatonKind::Sand,
+            palettes,
+            reset: None,
+            tool: settings.tool,
+            brush_size: settings.brush_size,
             offset: 0,
-            speed,
+            speed: normalize_speed_down(speed),
             distance: 0.0,
+            paused: options.paused,
+            pending_steps: 0,
+            run_steps: 100,
+            step: 0,
             last_frame: now,
-            zoom: 1.0,
+            zoom: settings.zoom.clamp(MIN_ZOOM, MAX_ZOOM),
+            cell_size,
+            linear_filtering: settings.linear_filtering,
+            bloom_strength: settings.bloom_strength,
+            scale_factor: 1.0,
             debug,
+            render_mode: RenderMode::Normal,
+            isolated_material: ParticleType::Sand,
+            tiled: false,
+            validate_tiled: false,
+            validation: None,
+            inspection: None,
+            statistics: Statistics::new(),
         }
     }
 
-    pub fn resize(&mut self, size: PhysicalSize<u32>) {
-        self.size = PhysicalSize {
-            width: size.width - UI_SIDEBAR_WIDTH,
-            height: size.height - UI_MENU_HEIGHT,
+    pub fn settings(&self) -> ControllerSettings {
+        let sand = self.palettes[AutomatonKind::Sand as usize];
+        ControllerSettings {
+            brush_size: self.brush_size,
+            speed: normalize_speed_up(self.speed),
+            primary_material: ParticleType::from_value(sand.primary),
+            secondary_material: ParticleType::from_value(sand.secondary),
+            tool: self.tool,
+            zoom: self.zoom,
+            cell_size: self.cell_size,
+            linear_filtering: self.linear_filtering,
+            bloom_strength: self.bloom_strength,
+            debug: self.debug,
+        }
+    }
+
+    pub fn apply_settings(&mut self, settings: &ControllerSettings) {
+        self.brush_size = settings.brush_size;
+        self.speed = normalize_speed_down(settings.speed);
+        *self.sand_palette() = Palette {
+            primary: settings.primary_material as u32,
+            secondary: settings.secondary_material as u32,
         };
+        self.tool = settings.tool;
+        self.zoom = settings.zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.cell_size = settings.cell_size.clamp(1.0, 16.0);
+        self.linear_filtering = settings.linear_filtering;
+        self.bloom_strength = settings.bloom_strength;
+        self.debug = settings.debug;
+    }
+
+    /// Follows the viewport or cell size with the grid size, unless the world has a fixed
+    /// size. The viewport changes with the window and the sidebar. Returns whether the
+    /// grid size changed, so that the simulation can follow it.
+    pub fn update_grid_size(&mut self) -> bool {
+        let size = self
+            .world_size
+            .unwrap_or_else(|| grid_size_for(self.viewport_size, self.cell_size));
+        if size == self.size {
+            return false;
+        }
+        self.size = size;
+        self.grid = new_grid(self.automaton, size);
+        self.inspection = None;
+        true
+    }
+
+    /// Starts the next simulation with `grid` instead of an empty grid, unless the grid
+    /// was resized since it was read back
+    pub fn restore_grid(&mut self, grid: Grid<Particle>) {
+        if uvec2(grid.w as u32, grid.h as u32) == self.grid_size() {
+            self.grid = grid;
+        } else {
+            eprintln!("Discarding the grid backup, its size no longer matches");
+        }
+    }
+
+    /// Sets the area of the window not covered by the ui, in physical pixels
+    pub fn set_viewport(&mut self, offset: Vec2, size: Vec2) {
+        self.viewport_offset = offset;
+        self.viewport_size = size;
+    }
+
+    pub fn viewport(&self) -> (Vec2, Vec2) {
+        (self.viewport_offset, self.viewport_size)
     }
 
     pub fn mouse_move(&mut self, position: PhysicalPosition<f64>) {
-        self.cursor = vec2(position.x as f32, position.y as f32 - UI_MENU_HEIGHT as f32);
+        self.cursor = vec2(position.x as f32, position.y as f32);
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor as f32;
+    }
+
+    /// Physical pixels per cell, combining zoom and cell size
+    pub fn pixels_per_cell(&self) -> f32 {
+        self.zoom * self.cell_size
+    }
+
+    /// Converts a position relative to the viewport into grid coordinates
+    fn zoomed(&self, p: Vec2) -> Vec2 {
+        let height = self.size.height as f32;
+        let zoom = self.pixels_per_cell();
+        p / zoom + Vec2::Y * (height - self.viewport_size.y / zoom)
     }
 
     pub fn mouse_scroll(&mut self, delta: MouseScrollDelta) {
@@ -88,88 +263,268 @@ impl Controller {
             MouseScrollDelta::LineDelta(_, val) => val * 0.1,
             MouseScrollDelta::PixelDelta(p) => (p.y * 0.005) as f32,
         };
-        self.zoom = (self.zoom + self.zoom * val).clamp(1.0, 100.0);
+        self.zoom(val);
+    }
+
+    fn zoom(&mut self, val: f32) {
+        self.zoom = (self.zoom + self.zoom * val).clamp(MIN_ZOOM, MAX_ZOOM);
     }
 
     pub fn mouse_input(&mut self, state: ElementState, button: MouseButton) {
-        if button == MouseButton::Left {
-            self.cursor_down = match state {
-                ElementState::Pressed => true,
-                ElementState::Released => false,
-            };
-        } else if button == MouseButton::Right {
-            self.cursor_right_down = match state {
-                ElementState::Pressed => true,
-                ElementState::Released => false,
-            };
+        match state {
+            ElementState::Pressed => {
+                if let Some(slot) = self.bindings.mouse_slot(button) {
+                    self.pressed = Some((button, slot));
+                }
+            }
+            ElementState::Released => {
+                if self.pressed.is_some_and(|(b, _)| b == button) {
+                    self.pressed = None;
+                }
+            }
         }
     }
 
     pub fn keyboard_input(&mut self, key: KeyEvent) {
-        match key.logical_key {
-            Key::Character(x) if x.as_str() == "x" => {
-                if key.state.is_pressed() {
-                    self.distance += 1.0;
-                };
-            }
-            _ => {}
+        if !key.state.is_pressed() {
+            return;
+        }
+        let Some(action) = self.bindings.key_action(&key.logical_key) else {
+            return;
+        };
+        match action {
+            Action::Pause if !key.repeat => self.toggle_pause(),
+            Action::Pause => {}
+            Action::Step => self.step(1),
+            Action::StepPair => self.step_pair(),
+            Action::BrushGrow => self.brush_size = (self.brush_size * 1.25).min(1000.0),
+            Action::BrushShrink => self.brush_size = (self.brush_size / 1.25).max(1.0),
+            Action::ZoomIn => self.zoom(0.25),
+            Action::ZoomOut => self.zoom(-0.2),
+            Action::Tool(tool) => self.tool = tool,
+            Action::Material(material) => self.sand_palette().primary = material as u32,
+            Action::SecondaryMaterial(material) => self.sand_palette().secondary = material as u32,
+        }
+    }
+
+    /// The bits of the painted state, which for the falling sand is the material
+    fn painted_value(&self) -> u32 {
+        let palette = self.palettes[self.automaton as usize];
+        match (self.tool, self.pressed) {
+            (Tool::Eraser, _) => self.automaton.rules().states()[0].bits,
+            (Tool::Brush, Some((_, Slot::Secondary))) => palette.secondary,
+            (Tool::Brush, _) => palette.primary,
         }
     }
 
+    /// The bits the canvas pass paints, while the brush is down
+    pub fn painting(&self) -> Option<u32> {
+        self.pressed.map(|_| self.painted_value())
+    }
+
+    /// The painted materials, which the settings and bindings refer to
+    fn sand_palette(&mut self) -> &mut Palette {
+        &mut self.palettes[AutomatonKind::Sand as usize]
+    }
+
     pub fn pre_render(&mut self) {
-        let particle_type = if self.cursor_right_down {
-            ParticleType::Empty
-        } else {
-            self.current_particle_type
-        };
+        // Brush size is in logical pixels, the shader works in cells
+        let brush_size = self.brush_size * self.scale_factor / self.pixels_per_cell();
         self.fragment_constants = FragmentConstants {
-            size: self.size.into(),
+            size: self.grid_size(),
+            viewport_offset: self.viewport_offset,
+            viewport_size: self.viewport_size,
             time: self.start.elapsed().as_secs_f32(),
-            cursor_down: (self.cursor_down || self.cursor_right_down).into(),
-            cursor: self.cursor.into(),
-            prev_cursor: self.prev_cursor.into(),
-            current_particle_type: particle_type as u32,
-            brush_size_sq: self.brush_size * self.brush_size / (self.zoom * self.zoom),
+            cursor_down: self.pressed.is_some().into(),
+            cursor: self.cursor - self.viewport_offset,
+            prev_cursor: self.prev_cursor - self.viewport_offset,
+            current_particle_type: self.painted_value(),
+            brush_size_sq: brush_size * brush_size,
             offset: self.offset,
-            zoom: self.zoom,
+            zoom: self.pixels_per_cell(),
             debug: self.debug.into(),
+            render_mode: if self.debug {
+                self.render_mode as u32
+            } else {
+                RenderMode::Normal as u32
+            },
+            isolated_material: self.isolated_material as u32,
         };
         self.prev_cursor = self.cursor;
     }
 
-    pub fn pre_update(&mut self) {
-        self.compute_constants = ComputeConstants {
-            size: self.size.into(),
-            time: self.start.elapsed().as_secs_f32(),
-            offset: self.offset,
-            zoom: self.zoom,
-        };
+    /// Follows the simulation after it stepped
+    pub fn set_progress(&mut self, offset: u32, step: u64) {
+        self.offset = offset;
+        self.step = step;
     }
 
-    pub fn post_update(&mut self) {
-        self.offset = 1 - self.offset;
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
     }
 
-    pub fn fragment_constants(&self) -> &[u8] {
-        bytemuck::bytes_of(&self.fragment_constants)
+    /// Queues `n` Margolus sub-steps, which run even while paused
+    pub fn step(&mut self, n: u32) {
+        self.pending_steps += n;
     }
 
-    pub fn compute_constants(&self) -> &[u8] {
-        bytemuck::bytes_of(&self.compute_constants)
+    /// Queues the sub-steps needed to finish the current pair of offsets
+    pub fn step_pair(&mut self) {
+        let pending_offset = (self.offset + self.pending_steps) % 2;
+        self.step(2 - pending_offset);
+    }
+
+    pub fn simulation_menu(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .button(if self.paused { "Resume" } else { "Pause" })
+            .clicked()
+        {
+            self.toggle_pause();
+            ui.close_menu();
+        }
+        if ui.button("Step").clicked() {
+            self.step(1);
+            ui.close_menu();
+        }
+        if ui.button("Step pair").clicked() {
+            self.step_pair();
+            ui.close_menu();
+        }
+        if ui.button(format!("Run {} steps", self.run_steps)).clicked() {
+            self.step(self.run_steps);
+            ui.close_menu();
+        }
+    }
+
+    fn step_controls_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .button(if self.paused { "Resume" } else { "Pause" })
+                .clicked()
+            {
+                self.toggle_pause();
+            }
+            if ui.button("Step").clicked() {
+                self.step(1);
+            }
+            if ui.button("Pair").clicked() {
+                self.step_pair();
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Run").clicked() {
+                self.step(self.run_steps);
+            }
+            ui.add(egui::DragValue::new(&mut self.run_steps).range(1..=100000));
+            ui.label("steps");
+        });
+        ui.label(format!("Step: {}", self.step));
+    }
+
+    pub fn automaton(&self) -> AutomatonKind {
+        self.automaton
+    }
+
+    /// Switches to `automaton`, which starts from a cleared grid
+    fn set_automaton(&mut self, automaton: AutomatonKind) {
+        self.automaton = automaton;
+        self.grid = new_grid(automaton, self.size);
+        self.inspection = None;
+        self.reset = Some(GridReset::Clear);
+    }
+
+    /// Whether the grid should be replaced, after switching automata or from the UI
+    pub fn take_reset(&mut self) -> Option<GridReset> {
+        self.reset.take()
+    }
+
+    pub fn grid_size(&self) -> UVec2 {
+        uvec2(self.size.width, self.size.height)
+    }
+
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.step
+    }
+
+    /// The cell under the cursor, while in debug mode
+    pub fn inspected_cell(&self) -> Option<UVec2> {
+        if !self.debug {
+            return None;
+        }
+        let pos = self.zoomed(self.cursor - self.viewport_offset);
+        if pos.cmplt(Vec2::ZERO).any() || pos.cmpge(self.grid_size().as_vec2()).any() {
+            return None;
+        }
+        Some(pos.as_uvec2())
+    }
+
+    pub fn set_inspection(&mut self, inspection: Option<Inspection>) {
+        self.inspection = inspection;
+    }
+
+    pub fn statistics(&mut self) -> &mut Statistics {
+        &mut self.statistics
+    }
+
+    pub fn tiled(&self) -> bool {
+        self.tiled
+    }
+
+    /// Whether a comparison of the tiled shader against the CPU was requested
+    pub fn take_validate_tiled(&mut self) -> bool {
+        std::mem::take(&mut self.validate_tiled)
+    }
+
+    pub fn set_validation(&mut self, validation: String) {
+        self.validation = Some(validation);
+    }
+
+    pub fn linear_filtering(&self) -> bool {
+        self.linear_filtering
+    }
+
+    pub fn bloom_strength(&self) -> f32 {
+        self.bloom_strength
+    }
+
+    pub fn fragment_constants(&self) -> &[u8] {
+        bytemuck::bytes_of(&self.fragment_constants)
     }
 
     pub fn ui(
         &mut self,
-        _ctx: &Context,
+        ctx: &Context,
         ui: &mut egui::Ui,
         _event_proxy: &EventLoopProxy<UserEvent>,
     ) {
-        ui.radio_value(&mut self.current_particle_type, ParticleType::Sand, "Sand");
-        ui.radio_value(
-            &mut self.current_particle_type,
-            ParticleType::Water,
-            "Water",
-        );
+        let mut automaton = self.automaton;
+        egui::ComboBox::from_label("Automaton")
+            .selected_text(automaton.name())
+            .show_ui(ui, |ui| {
+                for kind in AutomatonKind::iter() {
+                    ui.selectable_value(&mut automaton, kind, kind.name());
+                }
+            });
+        if automaton != self.automaton {
+            self.set_automaton(automaton);
+        }
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.tool, Tool::Brush, "Brush");
+            ui.selectable_value(&mut self.tool, Tool::Eraser, "Eraser");
+        });
+        self.states_ui(ui);
+        ui.horizontal(|ui| {
+            if ui.button("Clear").clicked() {
+                self.reset = Some(GridReset::Clear);
+            }
+            if self.automaton.rules().seed_state().is_some() && ui.button("Randomize").clicked() {
+                self.reset = Some(GridReset::Randomize);
+            }
+        });
         ui.add(egui::Label::new("       Brush Size").selectable(false));
         ui.add(egui::Slider::new(&mut self.brush_size, 1.0..=1000.0).logarithmic(true));
         ui.add(egui::Label::new(" Simulation Speed").selectable(false));
@@ -180,40 +535,126 @@ impl Controller {
         );
         ui.add(egui::Label::new("           Zoom").selectable(false));
         ui.add(
-            egui::Slider::new(&mut self.zoom, 1.0..=100.0)
+            egui::Slider::new(&mut self.zoom, MIN_ZOOM..=MAX_ZOOM)
                 .logarithmic(true)
                 .max_decimals(2),
         );
+        ui.add(egui::Label::new("       Cell Size").selectable(false))
+            .on_hover_text("Physical pixels per cell at zoom 1");
+        ui.add(egui::Slider::new(&mut self.cell_size, 1.0..=16.0).max_decimals(2));
+        ui.checkbox(&mut self.linear_filtering, "Smooth Cells");
+        ui.add(egui::Label::new("           Bloom").selectable(false))
+            .on_hover_text("Glow around emissive materials");
+        ui.add(egui::Slider::new(&mut self.bloom_strength, 0.0..=2.0).max_decimals(2));
+        self.step_controls_ui(ui);
+        let materials = self.automaton.rules().materials();
+        if materials {
+            self.statistics.ui(ui);
+        }
         ui.checkbox(&mut self.debug, "Debug");
         if self.debug {
             ui.label(format!("Elapsed: {:.1}s", self.start.elapsed().as_secs_f64()));
         }
+        if self.debug && materials {
+            egui::ComboBox::from_label("Render Mode")
+                .selected_text(self.render_mode.name())
+                .show_ui(ui, |ui| {
+                    for render_mode in RenderMode::iter() {
+                        ui.selectable_value(&mut self.render_mode, render_mode, render_mode.name());
+                    }
+                });
+            ui.checkbox(&mut self.tiled, "Tiled Compute")
+                .on_hover_text(format!(
+                    "Run {} sub-steps per dispatch from workgroup memory",
+                    tile::STEPS
+                ));
+            if ui.button("Validate Tiled").clicked() {
+                self.validate_tiled = true;
+            }
+            if let Some(validation) = &self.validation {
+                ui.label(validation);
+            }
+            if self.render_mode == RenderMode::Isolate {
+                egui::ComboBox::from_label("Isolated")
+                    .selected_text(self.isolated_material.name())
+                    .show_ui(ui, |ui| {
+                        for particle_type in ParticleType::iter() {
+                            ui.selectable_value(
+                                &mut self.isolated_material,
+                                particle_type,
+                                particle_type.name(),
+                            );
+                        }
+                    });
+            }
+        }
+        if self.debug {
+            if let Some(inspection) = &self.inspection {
+                if !ctx.is_pointer_over_area() {
+                    egui::show_tooltip_at_pointer(
+                        ctx,
+                        ui.layer_id(),
+                        egui::Id::new("inspector"),
+                        |ui| self.automaton.rules().inspector_ui(ui, inspection),
+                    );
+                }
+            }
+        }
     }
 
-    pub fn buffers(&self) -> BufferData {
-        BufferData {
-            bind_group_buffers: vec![BindGroupBufferType::SSBO(SSBO {
-                data: bytemuck::cast_slice(&self.grid.buffer[..]),
-                read_only: false,
-            })],
+    fn states_ui(&mut self, ui: &mut egui::Ui) {
+        let states = self.automaton.rules().states();
+        let palette = &mut self.palettes[self.automaton as usize];
+        for state in &states[1..] {
+            ui.radio_value(&mut palette.primary, state.bits, state.name);
         }
+        let secondary = states.iter().find(|state| state.bits == palette.secondary);
+        egui::ComboBox::from_label("Secondary")
+            .selected_text(secondary.map_or("", |state| state.name))
+            .show_ui(ui, |ui| {
+                for state in states {
+                    ui.selectable_value(&mut palette.secondary, state.bits, state.name);
+                }
+            });
+    }
+
+    pub fn grid(&self) -> &Grid<Particle> {
+        &self.grid
     }
 
     pub fn iterations(&mut self) -> u32 {
         let speed = normalize_speed_up(self.speed);
         let t = self.last_frame.elapsed().as_secs_f32() * 100.0;
         self.last_frame = Instant::now();
+        let pending_steps = self.pending_steps.min(MAX_PENDING_STEPS_PER_FRAME);
+        self.pending_steps -= pending_steps;
+        if self.paused {
+            return pending_steps;
+        }
         self.distance += speed * t;
         if self.distance >= 1.0 {
             let iterations = self.distance as u32;
             self.distance = self.distance.fract();
-            iterations
+            iterations + pending_steps
         } else {
-            0
+            pending_steps
         }
     }
 }
 
+/// Cells covering a viewport of `viewport_size` physical pixels at zoom 1
+fn grid_size_for(viewport_size: Vec2, cell_size: f32) -> PhysicalSize<u32> {
+    let size = (viewport_size / cell_size)
+        .ceil()
+        .as_uvec2()
+        .max(UVec2::splat(2));
+    PhysicalSize::new(size.x, size.y)
+}
+
+fn new_grid(automaton: AutomatonKind, size: PhysicalSize<u32>) -> Grid<Particle> {
+    automaton.new_grid(size.width as usize, size.height as usize, false)
+}
+
 fn normalize_speed_down(x: f32) -> f32 {
     (x / 25.0).sqrt()
 }
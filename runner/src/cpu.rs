@@ -0,0 +1,159 @@
+use glam::*;
+use shared::automata::{self, Automaton};
+use shared::electrics;
+use shared::grid::Grid;
+use shared::gridref::GridRef;
+use shared::particle::Particle;
+use shared::tile::{self, TileRef};
+use shared::update::step_block;
+
+/// Runs one sub-step over the whole grid, the same way `main_cs` does
+pub fn step(grid: &mut Grid<Particle>, offset: u32) {
+    let (w, h) = (grid.w as u32, grid.h as u32);
+    let mut grid = grid.as_ref_mut();
+    for y in (offset..h.saturating_sub(1)).step_by(2) {
+        for x in (offset..w.saturating_sub(1)).step_by(2) {
+            step_block(uvec2(x, y), &mut grid);
+        }
+    }
+}
+
+/// Runs `sub_steps` sub-steps after the first `steps`, with the electronics stepped
+/// between runs the way the untiled compute pass does, see `shared::electrics`
+#[cfg(feature = "app")]
+pub fn step_untiled(grid: &mut Grid<Particle>, steps: u64, sub_steps: u32) {
+    for step in steps..steps + sub_steps as u64 {
+        self::step(grid, (step % 2) as u32);
+        if (step + 1) % electrics::RUN as u64 == 0 {
+            for _ in 0..electrics::STEPS_PER_RUN {
+                step_electrics(grid);
+            }
+        }
+    }
+}
+
+/// Runs one step of the electronics the way `electrics_cs` does, every cell at once from
+/// a copy of the grid
+pub fn step_electrics(grid: &mut Grid<Particle>) {
+    let size = uvec2(grid.w as u32, grid.h as u32);
+    let particles = grid.buffer.clone();
+    let cells = GridRef::new(grid.w, grid.h, &particles);
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let cell = electrics::step_cell(&cells, size, uvec2(x, y));
+            grid.set(x as usize, y as usize, cell);
+        }
+    }
+}
+
+/// Runs one step of an automaton from `automata` the way its step shader does, every
+/// cell at once from a copy of the grid
+pub fn step_automaton<A: Automaton>(grid: &mut Grid<Particle>) {
+    let size = uvec2(grid.w as u32, grid.h as u32);
+    let bits: Vec<u32> = bytemuck::cast_slice(&grid.buffer).to_vec();
+    let cells = GridRef::new(grid.w, grid.h, &bits);
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let cell = automata::step_cell::<A>(&cells, size, uvec2(x, y));
+            grid.set(x as usize, y as usize, Particle::from_bits(cell.to_bits()));
+        }
+    }
+}
+
+/// Runs `tile::STEPS` sub-steps the way `main_tiled_cs` does, one workgroup at a time
+pub fn step_tiled(grid: &Grid<Particle>, offset: u32) -> Grid<Particle> {
+    let size = uvec2(grid.w as u32, grid.h as u32);
+    let mut out = Grid {
+        w: grid.w,
+        h: grid.h,
+        buffer: grid.buffer.clone(),
+    };
+    for wy in 0..size.y.div_ceil(tile::TILE) {
+        for wx in 0..size.x.div_ceil(tile::TILE) {
+            let origin = tile::region_origin(uvec2(wx, wy));
+            let mut cells = [Particle::empty_from_tone(0.0); tile::REGION_LEN];
+            for (i, cell) in cells.iter_mut().enumerate() {
+                let pos = origin + tile::region_cell(i as u32).as_ivec2();
+                if tile::in_grid(pos, size) {
+                    *cell = grid.get(pos.x as usize, pos.y as usize);
+                }
+            }
+            let mut region = TileRef::new(&mut cells);
+            for step in 0..tile::STEPS {
+                let offset = (offset + step) % 2;
+                for block in 0..tile::BLOCKS {
+                    tile::step_region_block(&mut region, origin, size, offset, block);
+                }
+            }
+            for i in 0..tile::TILE_LEN as u32 {
+                let local = tile::tile_cell(i);
+                let pos = origin + local.as_ivec2();
+                if tile::in_grid(pos, size) {
+                    let cell = cells[(local.y * tile::REGION + local.x) as usize];
+                    out.set(pos.x as usize, pos.y as usize, cell);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Number of cells that differ, including their flags
+#[cfg(any(test, feature = "app"))]
+pub fn mismatches(a: &Grid<Particle>, b: &Grid<Particle>) -> usize {
+    a.buffer
+        .iter()
+        .zip(&b.buffer)
+        .filter(|(a, b)| a.to_bits() != b.to_bits())
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use shared::particle::ParticleType;
+
+    fn random_grid(rng: &mut StdRng, w: usize, h: usize) -> Grid<Particle> {
+        let types = [
+            ParticleType::Empty,
+            ParticleType::Sand,
+            ParticleType::Water,
+            ParticleType::Conductor,
+        ];
+        let buffer = (0..w * h)
+            .map(|_| Particle::new_from_tone(types[rng.gen_range(0..types.len())], rng.gen()))
+            .collect();
+        Grid { w, h, buffer }
+    }
+
+    #[test]
+    fn tiled_matches_untiled() {
+        let mut rng = StdRng::seed_from_u64(0);
+        // Sizes that are not multiples of the tile leave partial tiles at the edges
+        let tile = tile::TILE as usize;
+        let sizes = [
+            (64, 64),
+            (77, 45),
+            (2, 2),
+            (130, 9),
+            (tile + 1, tile - 1),
+            (3 * tile + 2, 2 * tile + 1),
+        ];
+        for (w, h) in sizes {
+            for offset in [0, 1] {
+                let grid = random_grid(&mut rng, w, h);
+                let tiled = step_tiled(&grid, offset);
+                let mut untiled = grid;
+                for i in 0..tile::STEPS {
+                    step(&mut untiled, (offset + i) % 2);
+                }
+                assert_eq!(
+                    mismatches(&tiled, &untiled),
+                    0,
+                    "{w}x{h} at offset {offset}"
+                );
+            }
+        }
+    }
+}
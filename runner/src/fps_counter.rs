@@ -1,5 +1,10 @@
+use crate::profiler::{Pass, Timings};
+use egui::{Color32, Sense, Shape, Stroke};
 use std::collections::VecDeque;
-use std::time::{Duration, Instant};
+use std::time::Duration;
+use web_time::Instant;
+
+const HISTORY_LEN: usize = 120;
 
 pub struct FpsCounter {
     frames: VecDeque<Instant>,
@@ -29,3 +34,77 @@ impl FpsCounter {
     }
 }
 
+/// Recent timings shown next to the FPS counter
+pub struct GpuTimes {
+    history: VecDeque<Timings>,
+}
+
+impl GpuTimes {
+    pub fn new() -> Self {
+        Self {
+            history: VecDeque::with_capacity(HISTORY_LEN),
+        }
+    }
+
+    pub fn push(&mut self, timings: Timings) {
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(timings);
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    /// Graph and per-pass times, for a right to left layout
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        self.graph(ui);
+        let latest = self.history.back().copied().unwrap_or_default();
+        for pass in Pass::iter().rev() {
+            ui.colored_label(
+                color(pass),
+                format!("{}: {:.2} ms", pass.name(), latest[pass as usize]),
+            );
+        }
+    }
+
+    fn graph(&self, ui: &mut egui::Ui) {
+        let size = egui::vec2(HISTORY_LEN as f32, ui.spacing().interact_size.y);
+        let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_stroke(rect, 0.0, ui.visuals().widgets.noninteractive.bg_stroke);
+
+        let max = self
+            .history
+            .iter()
+            .flatten()
+            .copied()
+            .fold(0.0, f32::max)
+            .max(0.01);
+        let dx = rect.width() / (HISTORY_LEN - 1) as f32;
+        for pass in Pass::iter() {
+            let points = self
+                .history
+                .iter()
+                .enumerate()
+                .map(|(i, timings)| {
+                    let y = timings[pass as usize] / max;
+                    egui::pos2(
+                        rect.left() + i as f32 * dx,
+                        rect.bottom() - y * rect.height(),
+                    )
+                })
+                .collect();
+            painter.add(Shape::line(points, Stroke::new(1.0, color(pass))));
+        }
+    }
+}
+
+fn color(pass: Pass) -> Color32 {
+    match pass {
+        Pass::Compute => Color32::from_rgb(230, 160, 60),
+        Pass::Grid => Color32::from_rgb(90, 170, 240),
+        Pass::Ui => Color32::from_rgb(150, 210, 110),
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{context::GraphicsContext, readback::Ring};
+use glam::*;
+use shared::particle::Particle;
+
+/// Number of cells read back on each side of the hovered cell.
+/// A radius of 1 always covers the whole Margolus block of the hovered cell.
+const RADIUS: u32 = 1;
+const SIDE: u32 = RADIUS * 2 + 1;
+const PARTICLE_SIZE: u64 = std::mem::size_of::<Particle>() as u64;
+
+pub struct Inspection {
+    pub cell: UVec2,
+    pub offset: u32,
+    origin: UVec2,
+    end: UVec2,
+    cells: Vec<Particle>,
+}
+
+impl Inspection {
+    /// Top left cell of the Margolus block containing the hovered cell
+    pub fn block(&self) -> IVec2 {
+        let offset = self.offset as i32;
+        (self.cell.as_ivec2() - offset).div_euclid(IVec2::splat(2)) * 2 + offset
+    }
+
+    pub fn get(&self, pos: IVec2) -> Option<Particle> {
+        if pos.cmplt(self.origin.as_ivec2()).any() || pos.cmpge(self.end.as_ivec2()).any() {
+            return None;
+        }
+        let local = pos - self.origin.as_ivec2();
+        self.cells
+            .get((local.y as u32 * SIDE + local.x as u32) as usize)
+            .copied()
+    }
+
+    pub fn particle(&self) -> Particle {
+        self.get(self.cell.as_ivec2()).unwrap()
+    }
+}
+
+/// Copies of the hovered cells on their way back, enough to start one every frame
+const STAGING_BUFFERS: usize = 3;
+
+pub struct Inspector {
+    staging: Ring<Inspection>,
+}
+
+impl Inspector {
+    pub fn new(ctx: &GraphicsContext) -> Self {
+        Self {
+            staging: Ring::new(
+                &ctx.device,
+                "Inspector Buffer",
+                (SIDE * SIDE) as u64 * PARTICLE_SIZE,
+                STAGING_BUFFERS,
+            ),
+        }
+    }
+
+    /// Starts copying the cells around `cell` out of the grid buffer, unless the earlier
+    /// copies are all still on their way. The result is picked up by `poll`.
+    pub fn inspect(
+        &mut self,
+        ctx: &GraphicsContext,
+        grid_buffer: &wgpu::Buffer,
+        size: UVec2,
+        cell: UVec2,
+        offset: u32,
+    ) {
+        let Some(staging) = self.staging.take_free() else {
+            return;
+        };
+        let origin = cell.saturating_sub(UVec2::splat(RADIUS));
+        let end = (cell + RADIUS + 1).min(size);
+        let width = end.x - origin.x;
+
+        let mut encoder = ctx
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Inspector Encoder"),
+            });
+        for y in origin.y..end.y {
+            encoder.copy_buffer_to_buffer(
+                grid_buffer,
+                (y * size.x + origin.x) as u64 * PARTICLE_SIZE,
+                &staging,
+                ((y - origin.y) * SIDE) as u64 * PARTICLE_SIZE,
+                width as u64 * PARTICLE_SIZE,
+            );
+        }
+        ctx.queue.submit(Some(encoder.finish()));
+
+        let inspection = Inspection {
+            cell,
+            offset,
+            origin,
+            end,
+            cells: Vec::new(),
+        };
+        self.staging.map(staging, inspection);
+    }
+
+    /// The latest inspection that arrived since the last call, without waiting for the
+    /// device
+    pub fn poll(
+        &mut self,
+        ctx: &GraphicsContext,
+    ) -> Option<Result<Inspection, wgpu::BufferAsyncError>> {
+        self.staging.poll(&ctx.device, |mut inspection, bytes| {
+            inspection.cells = bytemuck::cast_slice(bytes).to_vec();
+            inspection
+        })
+    }
+}
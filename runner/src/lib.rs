@@ -1,21 +1,57 @@
-use crate::{
-    shader::{maybe_watch, CompiledShaderModules},
-    user_event::UserEvent,
-};
-use egui_winit::winit::event_loop::EventLoop;
+//! The falling sand game. Without the default `app` feature only `simulation` is built,
+//! to embed the simulation without a window.
+
+#[cfg(feature = "app")]
+use crate::{scene::Scene, shader::CompiledShaderModules, user_event::UserEvent};
+#[cfg(feature = "app")]
+use egui_winit::winit::{dpi::PhysicalSize, event_loop::EventLoop};
+#[cfg(feature = "app")]
+use shared::particle::ParticleType;
+#[cfg(feature = "app")]
 use structopt::StructOpt;
 
+pub mod automaton;
+mod constants;
+mod cpu;
+mod readback;
+mod shader;
+pub mod simulation;
+
+#[cfg(feature = "app")]
 mod app;
-mod bind_group_buffer;
+#[cfg(feature = "app")]
+mod bindings;
+#[cfg(feature = "app")]
+mod bloom;
+#[cfg(feature = "app")]
+mod canvas;
+#[cfg(feature = "app")]
+mod config;
+#[cfg(feature = "app")]
 mod context;
+#[cfg(feature = "app")]
 mod controller;
+#[cfg(feature = "app")]
 mod fps_counter;
+#[cfg(feature = "app")]
+mod inspector;
+#[cfg(feature = "app")]
+mod profiler;
+#[cfg(feature = "app")]
 mod render_pass;
-mod shader;
+#[cfg(feature = "app")]
+mod scene;
+#[cfg(feature = "app")]
+mod settings;
+#[cfg(feature = "app")]
+mod statistics;
+#[cfg(feature = "app")]
 mod ui;
+#[cfg(feature = "app")]
 mod user_event;
 
-#[derive(StructOpt, Clone, Copy)]
+#[cfg(feature = "app")]
+#[derive(StructOpt, Clone)]
 #[structopt(name = "sandsim")]
 pub struct Options {
     // Default to true after the following is fixed
@@ -23,75 +59,259 @@ pub struct Options {
     #[structopt(long)]
     validate_spirv: bool,
 
+    /// Reads shader constants from a buffer even where push constants are supported
+    #[structopt(long)]
+    no_push_constants: bool,
+
     /// Starts in debug mode and with speed set to 0
     #[structopt(short, long)]
     debug: bool,
+
+    /// Initial size of the window in physical pixels, as WIDTHxHEIGHT
+    #[structopt(long, default_value = "1280x720", parse(try_from_str = parse_size))]
+    window_size: PhysicalSize<u32>,
+
+    /// Starts in borderless fullscreen
+    #[structopt(long)]
+    fullscreen: bool,
+
+    /// Size of the world in cells, as WIDTHxHEIGHT. Defaults to the size of the scene, or
+    /// else follows the part of the window beside the sidebar
+    #[structopt(long, parse(try_from_str = parse_world_size))]
+    world_size: Option<PhysicalSize<u32>>,
+
+    /// Text file placed at the bottom left of the world, one character per cell: `.` for
+    /// empty, `s` for sand, `w` for water, `c` for conductor, `h` for electron head and
+    /// `t` for electron tail
+    #[structopt(long, parse(try_from_str = Scene::load))]
+    scene: Option<Scene>,
+
+    /// Initial primary material, instead of the saved one
+    #[structopt(long, parse(try_from_str = parse_material))]
+    material: Option<ParticleType>,
+
+    /// Initial simulation speed, from 0 to 99, instead of the saved one
+    #[structopt(long, conflicts_with = "debug", parse(try_from_str = parse_speed))]
+    speed: Option<f32>,
+
+    /// Starts paused
+    #[structopt(long)]
+    paused: bool,
+
+    /// Prints the adapters of the selected backends and exits
+    #[structopt(long)]
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    list_adapters: bool,
+
+    /// Comma separated backends to pick the adapter from: vulkan, metal, dx12, gl or
+    /// webgpu. Defaults to WGPU_BACKEND, or else vulkan and metal
+    #[structopt(long, parse(try_from_str = parse_backends))]
+    backend: Option<wgpu::Backends>,
+
+    /// Index of the adapter in --list-adapters or part of its name, software adapters
+    /// such as llvmpipe included
+    #[structopt(long)]
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    adapter: Option<String>,
+}
+
+#[cfg(feature = "app")]
+impl Options {
+    /// Checks the options against each other, those on their own are checked while parsing
+    fn validate(&self) -> Result<(), String> {
+        let Some(scene) = &self.scene else {
+            return Ok(());
+        };
+        let size = scene.size();
+        match self.world_size {
+            Some(world_size) if size.x > world_size.width || size.y > world_size.height => {
+                Err(format!(
+                    "The scene is {}x{} cells, which does not fit into the {}x{} world",
+                    size.x, size.y, world_size.width, world_size.height
+                ))
+            }
+            Some(_) => Ok(()),
+            // The scene sets the size of the world
+            None => check_world_size(PhysicalSize::new(size.x, size.y)).map(|_| ()),
+        }
+    }
+
+    /// Fixed size of the world, if any
+    fn world_size(&self) -> Option<PhysicalSize<u32>> {
+        self.world_size.or_else(|| {
+            let size = self.scene.as_ref()?.size();
+            Some(PhysicalSize::new(size.x, size.y))
+        })
+    }
+}
+
+#[cfg(feature = "app")]
+fn parse_size(s: &str) -> Result<PhysicalSize<u32>, String> {
+    let parse = |s: &str| s.trim().parse::<u32>().ok().filter(|&x| x > 0);
+    s.split_once('x')
+        .and_then(|(width, height)| Some(PhysicalSize::new(parse(width)?, parse(height)?)))
+        .ok_or_else(|| format!("Expected WIDTHxHEIGHT with non-zero sizes, got {s:?}"))
 }
 
+#[cfg(feature = "app")]
+fn parse_world_size(s: &str) -> Result<PhysicalSize<u32>, String> {
+    check_world_size(parse_size(s)?)
+}
+
+/// `size` if its grid fits into the limits the device asks for. The adapter is not known
+/// yet, so this assumes the texture size of the default limits, which the device checks
+/// again when creating the grid.
+#[cfg(feature = "app")]
+fn check_world_size(size: PhysicalSize<u32>) -> Result<PhysicalSize<u32>, String> {
+    let limits = context::required_limits(wgpu::Limits::default(), false);
+    let max = limits.max_texture_dimension_2d;
+    let cells = size.width as u64 * size.height as u64;
+    let max_cells = limits.max_storage_buffer_binding_size as u64 / 4;
+    if size.width < 2 || size.height < 2 {
+        Err("The world has to be at least 2x2 cells".to_string())
+    } else if size.width > max || size.height > max {
+        Err(format!(
+            "The world can be at most {max} cells wide and high"
+        ))
+    } else if cells > max_cells {
+        Err(format!("The world can have at most {max_cells} cells"))
+    } else {
+        Ok(size)
+    }
+}
+
+#[cfg(feature = "app")]
+fn parse_backends(s: &str) -> Result<wgpu::Backends, String> {
+    const BACKENDS: [(&str, wgpu::Backends); 5] = [
+        ("vulkan", wgpu::Backends::VULKAN),
+        ("metal", wgpu::Backends::METAL),
+        ("dx12", wgpu::Backends::DX12),
+        ("gl", wgpu::Backends::GL),
+        ("webgpu", wgpu::Backends::BROWSER_WEBGPU),
+    ];
+    s.split(',')
+        .try_fold(wgpu::Backends::empty(), |backends, name| {
+            let name = name.trim();
+            BACKENDS
+                .iter()
+                .find(|(backend, _)| backend.eq_ignore_ascii_case(name))
+                .map(|&(_, backend)| backends | backend)
+                .ok_or_else(|| {
+                    let names: Vec<_> = BACKENDS.iter().map(|(name, _)| *name).collect();
+                    format!(
+                        "Unknown backend {name:?}, expected one of {}",
+                        names.join(", ")
+                    )
+                })
+        })
+}
+
+#[cfg(feature = "app")]
+fn parse_material(s: &str) -> Result<ParticleType, String> {
+    let materials = || ParticleType::iter().filter(|&t| t != ParticleType::Empty);
+    // Names of more than one word are written with hyphens, as in `electron-head`
+    let name = |t: ParticleType| t.name().to_lowercase().replace(' ', "-");
+    materials()
+        .find(|&t| name(t).eq_ignore_ascii_case(s))
+        .ok_or_else(|| {
+            let names: Vec<_> = materials().map(name).collect();
+            format!(
+                "Unknown material {s:?}, expected one of {}",
+                names.join(", ")
+            )
+        })
+}
+
+#[cfg(feature = "app")]
+fn parse_speed(s: &str) -> Result<f32, String> {
+    let speed: f32 = s.parse().map_err(|err| format!("{err}"))?;
+    if (0.0..=controller::MAX_SPEED).contains(&speed) {
+        Ok(speed)
+    } else {
+        Err(format!(
+            "The speed has to be between 0 and {}",
+            controller::MAX_SPEED
+        ))
+    }
+}
+
+#[cfg(feature = "app")]
 pub fn main() {
-    let options = Options::from_args();
+    #[allow(unused_mut)]
+    let mut options = Options::from_args();
+    if let Err(err) = options.validate() {
+        structopt::clap::Error::with_description(
+            &err,
+            structopt::clap::ErrorKind::ArgumentConflict,
+        )
+        .exit();
+    }
 
     cfg_if::cfg_if! {
         if #[cfg(target_arch = "wasm32")] {
             std::panic::set_hook(Box::new(console_error_panic_hook::hook));
             console_log::init().expect("could not initialize logger");
+            // The browser only accepts shaders that wgpu translated to WGSL
+            options.validate_spirv = true;
         } else {
             env_logger::init();
         }
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
+    if options.list_adapters {
+        context::list_adapters(&options);
+        return;
+    }
+
     let event_loop = EventLoop::with_user_event().build().unwrap();
 
-    // Build the shader before we pop open a window, since it might take a while.
-    let initial_shader = maybe_watch(
-        &options,
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            let proxy = event_loop.create_proxy();
-            Some(Box::new(move |res| {
-                match proxy.send_event(UserEvent::NewModule(res)) {
+    // Both variants are embedded, the one matching the adapter is picked once it is known
+    let initial_shader = shader::precompiled(!options.no_push_constants);
+    // With hot reloading, the embedded shaders run until the watcher built the current
+    // ones. Its variant is a guess from probing the adapters, and rebuilt if the window's
+    // adapter differs.
+    #[cfg(hot_reload)]
+    {
+        let push_constants = !options.no_push_constants && context::probe_push_constants(&options);
+        let proxy = event_loop.create_proxy();
+        shader::watch(
+            &options,
+            push_constants,
+            Box::new(move |res| {
+                let event = match res {
+                    Ok(module) => UserEvent::NewModule(module),
+                    Err(err) => UserEvent::ShaderBuildFailed(err),
+                };
+                match proxy.send_event(event) {
                     Ok(it) => it,
                     // ShaderModuleDescriptor is not `Debug`, so can't use unwrap/expect
                     Err(_err) => panic!("Event loop dead"),
                 }
-            }))
-        },
-    );
-
-    cfg_if::cfg_if! {
-        if #[cfg(target_arch = "wasm32")] {
-            use winit::platform::web::WindowExtWebSys;
-            // On wasm, append the canvas to the document body
-            web_sys::window()
-                .and_then(|win| win.document())
-                .and_then(|doc| doc.body())
-                .and_then(|body| {
-                    body.append_child(&web_sys::Element::from(window.canvas()))
-                        .ok()
-                })
-                .expect("couldn't append canvas to document body");
-            wasm_bindgen_futures::spawn_local(run(
-                options.clone(),
-                window,
-                initial_shader,
-            ));
-        } else {
-            run(
-                options,
-                event_loop,
-                initial_shader,
-            );
-        }
+            }),
+        );
     }
+
+    run(options, event_loop, initial_shader);
 }
 
+#[cfg(feature = "app")]
 fn run(
     options: Options,
     event_loop: EventLoop<UserEvent>,
     compiled_shader_modules: CompiledShaderModules,
 ) {
-    let mut app = app::App::new(event_loop.create_proxy(), compiled_shader_modules, options);
-    if let Result::Err(e) = event_loop.run_app(&mut app) {
-        eprintln!("Event loop Error: {e}")
+    let app = app::App::new(event_loop.create_proxy(), compiled_shader_modules, options);
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            // Returns immediately, the browser drives the event loop
+            use egui_winit::winit::platform::web::EventLoopExtWebSys;
+            event_loop.spawn_app(app);
+        } else {
+            let mut app = app;
+            if let Result::Err(e) = event_loop.run_app(&mut app) {
+                eprintln!("Event loop Error: {e}")
+            }
+        }
     }
 }
@@ -0,0 +1,154 @@
+use std::cell::Cell;
+
+const QUERY_COUNT: u32 = 2 * Pass::COUNT as u32;
+const QUERIES_SIZE: u64 = QUERY_COUNT as u64 * std::mem::size_of::<u64>() as u64;
+
+/// Passes timed by the profiler
+#[derive(Clone, Copy)]
+pub enum Pass {
+    /// Every simulation step of the frame
+    Compute,
+    /// The canvas compute pass through the fragment shader drawing the grid
+    Grid,
+    Ui,
+}
+
+impl Pass {
+    pub const COUNT: usize = 3;
+
+    pub fn iter() -> impl DoubleEndedIterator<Item = Self> {
+        [Self::Compute, Self::Grid, Self::Ui].into_iter()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Compute => "Compute",
+            Self::Grid => "Grid",
+            Self::Ui => "UI",
+        }
+    }
+
+    fn begin_index(self) -> u32 {
+        2 * self as u32
+    }
+
+    fn end_index(self) -> u32 {
+        2 * self as u32 + 1
+    }
+}
+
+/// GPU time of each pass in milliseconds
+pub type Timings = [f32; Pass::COUNT];
+
+/// Timestamp queries written at the beginning and end of the timed passes. Only
+/// available when the device was created with `TIMESTAMP_QUERY`.
+pub struct Profiler {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    buffer: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick
+    period: f32,
+    /// Passes that wrote their timestamps since the last read
+    written: Cell<[bool; Pass::COUNT]>,
+}
+
+impl Profiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Profiler Query Set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: QUERY_COUNT,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Profiler Resolve Buffer"),
+            size: QUERIES_SIZE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Profiler Buffer"),
+            size: QUERIES_SIZE,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            buffer,
+            period: queue.get_timestamp_period(),
+            written: Cell::new([false; Pass::COUNT]),
+        })
+    }
+
+    fn mark(&self, pass: Pass) {
+        let mut written = self.written.get();
+        written[pass as usize] = true;
+        self.written.set(written);
+    }
+
+    /// Timestamps for a compute pass. A pass can be timed across several compute
+    /// passes by only beginning in the first and only ending in the last.
+    pub fn compute_writes(
+        &self,
+        pass: Pass,
+        begin: bool,
+        end: bool,
+    ) -> wgpu::ComputePassTimestampWrites<'_> {
+        self.mark(pass);
+        wgpu::ComputePassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: begin.then_some(pass.begin_index()),
+            end_of_pass_write_index: end.then_some(pass.end_index()),
+        }
+    }
+
+    /// Timestamps for a render pass, see `compute_writes`
+    pub fn render_writes(
+        &self,
+        pass: Pass,
+        begin: bool,
+        end: bool,
+    ) -> wgpu::RenderPassTimestampWrites<'_> {
+        self.mark(pass);
+        wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: begin.then_some(pass.begin_index()),
+            end_of_pass_write_index: end.then_some(pass.end_index()),
+        }
+    }
+
+    /// Resolves the timestamps of the frame and waits for them. Passes that did not run
+    /// since the last read, such as the compute pass while paused, read as zero.
+    pub fn read(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Timings, wgpu::BufferAsyncError> {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Profiler Encoder"),
+        });
+        encoder.resolve_query_set(&self.query_set, 0..QUERY_COUNT, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.buffer, 0, QUERIES_SIZE);
+        queue.submit(Some(encoder.finish()));
+
+        let slice = self.buffer.slice(..);
+        crate::readback::map_blocking(device, slice)?;
+        let ticks: [u64; QUERY_COUNT as usize] =
+            bytemuck::pod_read_unaligned(&slice.get_mapped_range());
+        self.buffer.unmap();
+
+        let written = self.written.replace([false; Pass::COUNT]);
+        let mut timings = [0.0; Pass::COUNT];
+        for pass in Pass::iter() {
+            if written[pass as usize] {
+                let begin = ticks[pass.begin_index() as usize];
+                let end = ticks[pass.end_index() as usize];
+                timings[pass as usize] = end.saturating_sub(begin) as f32 * self.period / 1e6;
+            }
+        }
+        Ok(timings)
+    }
+}
@@ -0,0 +1,100 @@
+//! Copying buffers back from the device
+
+use std::{collections::VecDeque, sync::mpsc};
+
+/// Maps `slice` for reading and waits for the device, which the browser does not allow
+pub fn map_blocking(
+    device: &wgpu::Device,
+    slice: wgpu::BufferSlice,
+) -> Result<(), wgpu::BufferAsyncError> {
+    let mapped = map(slice);
+    device.poll(wgpu::Maintain::Wait);
+    mapped.recv().unwrap_or(Err(wgpu::BufferAsyncError))
+}
+
+/// Starts mapping `slice` for reading. The result arrives once the device is polled.
+pub fn map(slice: wgpu::BufferSlice) -> mpsc::Receiver<Result<(), wgpu::BufferAsyncError>> {
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        sender.send(result).ok();
+    });
+    receiver
+}
+
+/// Staging buffers that are copied into and mapped without waiting for the device, so
+/// that a copy can start every frame while the earlier ones are on their way. Each copy
+/// carries a `T` describing what was copied.
+pub struct Ring<T> {
+    /// Staging buffers free for the next copy
+    free: Vec<wgpu::Buffer>,
+    /// Copies being mapped, oldest first
+    pending: VecDeque<Pending<T>>,
+}
+
+struct Pending<T> {
+    buffer: wgpu::Buffer,
+    tag: T,
+    mapped: mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>,
+}
+
+impl<T> Ring<T> {
+    /// `len` staging buffers of `size` bytes
+    pub fn new(device: &wgpu::Device, label: &str, size: u64, len: usize) -> Self {
+        let free = (0..len)
+            .map(|_| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(label),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect();
+        Self {
+            free,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// A staging buffer to copy into, unless all of them are on their way. It must be
+    /// handed back with `map` once the copy was submitted.
+    pub fn take_free(&mut self) -> Option<wgpu::Buffer> {
+        self.free.pop()
+    }
+
+    /// Starts mapping `buffer`, which a submitted copy described by `tag` writes to
+    pub fn map(&mut self, buffer: wgpu::Buffer, tag: T) {
+        let mapped = map(buffer.slice(..));
+        self.pending.push_back(Pending {
+            buffer,
+            tag,
+            mapped,
+        });
+    }
+
+    /// The latest copy that arrived since the last call, read from its mapped bytes with
+    /// `read`. This polls `device` without waiting for it.
+    pub fn poll<R>(
+        &mut self,
+        device: &wgpu::Device,
+        read: impl Fn(T, &[u8]) -> R,
+    ) -> Option<Result<R, wgpu::BufferAsyncError>> {
+        device.poll(wgpu::Maintain::Poll);
+        let mut latest = None;
+        while let Some(pending) = self.pending.front() {
+            let mapped = match pending.mapped.try_recv() {
+                Ok(mapped) => mapped,
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => Err(wgpu::BufferAsyncError),
+            };
+            let Pending { buffer, tag, .. } = self.pending.pop_front().unwrap();
+            latest = Some(mapped.map(|()| {
+                let result = read(tag, &buffer.slice(..).get_mapped_range());
+                buffer.unmap();
+                result
+            }));
+            self.free.push(buffer);
+        }
+        latest
+    }
+}
@@ -1,120 +1,250 @@
 use crate::{
-    bind_group_buffer::{BindGroupBufferType, BufferData, SSBO},
+    automaton::{AutomatonKind, Rules},
+    bloom::{Bloom, BloomPipelines},
+    canvas::Canvas,
+    constants::ShaderConstants,
     context::GraphicsContext,
-    controller::Controller,
-    shader::CompiledShaderModules,
-    ui::{Ui, UiState},
-    Options,
+    controller::{Controller, GridReset},
+    cpu,
+    inspector::Inspector,
+    profiler::{Pass, Profiler, Timings},
+    shader::{self, entry_points as shaders, CompiledShaderModules},
+    simulation::{PendingCells, Simulation},
+    ui::UiOutput,
 };
-use egui_winit::winit::{dpi::PhysicalSize, window::Window};
-use wgpu::{util::DeviceExt, BindGroupLayout, TextureView};
-
-#[cfg(not(target_arch = "wasm32"))]
-mod shaders {
-    #[allow(non_upper_case_globals)]
-    pub const main_fs: &str = "main_fs";
-    #[allow(non_upper_case_globals)]
-    pub const main_vs: &str = "main_vs";
-    #[allow(non_upper_case_globals)]
-    pub const main_cs: &str = "main_cs";
-}
-#[cfg(target_arch = "wasm32")]
-mod shaders {
-    include!(concat!(env!("OUT_DIR"), "/entry_points.rs"));
-}
+use shared::{electrics, grid::Grid, particle::Particle, tile};
+use std::time::Duration;
+use web_time::Instant;
+use wgpu::{BindGroupLayout, TextureView};
+
+/// Readbacks wait for the device, which the browser does not allow
+const BLOCKING_READBACK: bool = cfg!(not(target_arch = "wasm32"));
+/// How often the grid is copied back, to restore it from if the device is lost
+const GRID_BACKUP_INTERVAL: Duration = Duration::from_secs(5);
 
 struct Pipelines {
     render: wgpu::RenderPipeline,
-    compute: wgpu::ComputePipeline,
+    /// Paint and colour pipelines of every automaton, indexed by `AutomatonKind`
+    paint: Vec<wgpu::ComputePipeline>,
+    color: Vec<wgpu::ComputePipeline>,
+    bloom: BloomPipelines,
 }
 
 struct PipelineLayouts {
     render: wgpu::PipelineLayout,
-    compute: wgpu::PipelineLayout,
+    canvas: wgpu::PipelineLayout,
+    bloom: wgpu::PipelineLayout,
+    composite: wgpu::PipelineLayout,
 }
 
 pub struct RenderPass {
     pipelines: Pipelines,
     pipeline_layouts: PipelineLayouts,
     ui_renderer: egui_wgpu::Renderer,
-    options: Options,
-    bind_groups: Vec<wgpu::BindGroup>,
+    simulation: Simulation,
+    inspector: Inspector,
+    sampled_bind_group_layout: BindGroupLayout,
+    canvas_bind_group_layout: BindGroupLayout,
+    canvas: Canvas,
+    bloom: Bloom,
+    profiler: Option<Profiler>,
+    profiling: bool,
+    shader_constants: ShaderConstants,
+    /// The modules the pipelines were created from, kept to recreate them on a new device
+    modules: CompiledShaderModules,
+    grid_backup: Option<Grid<Particle>>,
+    /// The next backup, while it is copied back
+    pending_backup: Option<PendingCells>,
+    last_backup: Instant,
 }
 
 impl RenderPass {
     pub fn new(
         ctx: &GraphicsContext,
         compiled_shader_modules: CompiledShaderModules,
-        options: Options,
-        buffer_data: &BufferData,
-    ) -> Self {
-        let bind_group_layouts = create_bind_group_layouts(ctx, buffer_data);
-        let pipeline_layouts = create_pipeline_layouts(ctx, &bind_group_layouts);
+        controller: &Controller,
+    ) -> Result<Self, String> {
+        let mut simulation = Simulation::with_modules(
+            ctx.device.clone(),
+            ctx.queue.clone(),
+            &compiled_shader_modules,
+            ctx.spirv_passthrough,
+            controller.automaton(),
+            controller.grid(),
+        )
+        .map_err(|err| err.to_string())?;
+        simulation.restore_progress(controller.steps());
+        let sampled_bind_group_layout = crate::bloom::create_sampled_bind_group_layout(ctx);
+        let canvas_bind_group_layout = crate::canvas::create_storage_bind_group_layout(ctx);
+        let shader_constants = ShaderConstants::new(&ctx.device, ctx.push_constants);
+        let pipeline_layouts = create_pipeline_layouts(
+            ctx,
+            simulation.grid_bind_group_layout(),
+            &sampled_bind_group_layout,
+            &canvas_bind_group_layout,
+            &shader_constants,
+        );
         let pipelines = create_pipeline(
-            &options,
+            ctx.spirv_passthrough,
             &ctx.device,
             &pipeline_layouts,
             ctx.config.format,
-            compiled_shader_modules,
-        );
-        let bind_groups = maybe_create_bind_groups(ctx, buffer_data, &bind_group_layouts);
+            &compiled_shader_modules,
+        )?;
 
         let ui_renderer = egui_wgpu::Renderer::new(&ctx.device, ctx.config.format, None, 1, false);
 
-        Self {
+        Ok(Self {
             pipelines,
             pipeline_layouts,
             ui_renderer,
-            options,
-            bind_groups,
+            simulation,
+            inspector: Inspector::new(ctx),
+            canvas: Canvas::new(
+                ctx,
+                controller.grid_size(),
+                &canvas_bind_group_layout,
+                &sampled_bind_group_layout,
+            ),
+            bloom: Bloom::new(ctx, &sampled_bind_group_layout),
+            profiler: Profiler::new(&ctx.device, &ctx.queue).filter(|_| BLOCKING_READBACK),
+            profiling: false,
+            shader_constants,
+            modules: compiled_shader_modules,
+            grid_backup: None,
+            pending_backup: None,
+            last_backup: Instant::now(),
+            sampled_bind_group_layout,
+            canvas_bind_group_layout,
+        })
+    }
+
+    /// Whether the device supports timestamp queries
+    pub fn profiling_supported(&self) -> bool {
+        self.profiler.is_some()
+    }
+
+    /// Enables timing the passes, from the next frame on
+    pub fn set_profiling(&mut self, enable: bool) {
+        self.profiling = enable;
+    }
+
+    fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref().filter(|_| self.profiling)
+    }
+
+    /// GPU times of the passes since the last call, if profiling
+    pub fn read_timings(&self, ctx: &GraphicsContext) -> Option<Timings> {
+        self.profiler().and_then(|profiler| {
+            profiler
+                .read(&ctx.device, &ctx.queue)
+                .map_err(|err| eprintln!("Failed to read the GPU times back: {err}"))
+                .ok()
+        })
+    }
+
+    /// Resizes the simulation to the grid size of `controller`, see `Simulation::resize`
+    pub fn resize_grid(&mut self, ctx: &GraphicsContext, controller: &Controller) {
+        if let Err(err) = self.simulation.resize(controller.grid_size()) {
+            eprintln!("Failed to resize the grid: {err}");
+            return;
         }
+        self.canvas = Canvas::new(
+            ctx,
+            controller.grid_size(),
+            &self.canvas_bind_group_layout,
+            &self.sampled_bind_group_layout,
+        );
     }
 
-    pub fn compute(
-        &mut self,
-        ctx: &GraphicsContext,
-        inner_size: &PhysicalSize<u32>,
-        controller: &Controller,
-    ) {
-        let m = inner_size.width / 2;
-        let n = inner_size.height / 2;
-        let w = glam::UVec3::new(16, 16, 1);
-        let x = ((m as f32) / (w.x as f32)).ceil() as u32;
-        let y = ((n as f32) / (w.y as f32)).ceil() as u32;
-        self.call(ctx, (x, y, 1), controller);
+    /// Switches the simulation to the automaton of `controller`, then clears or
+    /// randomizes the grid. Backups of the previous grid are dropped.
+    pub fn reset_grid(&mut self, controller: &Controller, reset: GridReset) {
+        self.grid_backup = None;
+        self.pending_backup = None;
+        let automaton = controller.automaton();
+        let size = controller.grid_size();
+        let grid = automaton.new_grid(
+            size.x as usize,
+            size.y as usize,
+            reset == GridReset::Randomize,
+        );
+        self.simulation.set_automaton(automaton);
+        if let Err(err) = self.simulation.set_cells(&grid) {
+            eprintln!("Failed to reset the grid: {err}");
+        }
     }
 
-    pub fn call(
-        &mut self,
-        ctx: &GraphicsContext,
-        workspace: (u32, u32, u32),
-        controller: &Controller,
-    ) {
-        let mut encoder = ctx
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        {
-            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: None,
-                timestamp_writes: None,
-            });
+    /// Runs `iterations` simulation steps, recorded into a single submission
+    pub fn compute(&mut self, controller: &mut Controller, iterations: u32) {
+        // Only timed if the steps run, a pass without them would read stale timestamps
+        let timestamp_writes = self
+            .profiler
+            .as_ref()
+            .filter(|_| self.profiling && iterations > 0)
+            .map(|p| p.compute_writes(Pass::Compute, true, true));
+        self.simulation
+            .step_with(iterations, controller.tiled(), timestamp_writes);
+        controller.set_progress(self.simulation.offset(), self.simulation.steps());
+    }
+
+    /// Runs one tiled dispatch and compares the result with the untiled path, and with
+    /// the tiled path emulated on the CPU. Runs untiled sub-steps first if needed, to
+    /// align the dispatch to a multiple of `tile::STEPS`.
+    pub fn validate_tiled(&mut self, controller: &mut Controller) {
+        if !BLOCKING_READBACK {
+            controller.set_validation("Not available on the web".to_string());
+            return;
+        }
+        if !self.simulation.automaton().rules().materials() {
+            controller.set_validation("Only the falling sand is tiled".to_string());
+            return;
+        }
+        let misaligned = self.simulation.steps() % tile::STEPS as u64;
+        if misaligned != 0 {
+            self.simulation
+                .step_with(tile::STEPS - misaligned as u32, false, None);
+        }
+        let steps = self.simulation.steps();
+        let Ok(before) = self.simulation.cells() else {
+            controller.set_validation("Failed to read the grid back".to_string());
+            return;
+        };
+        self.simulation.step_with(tile::STEPS, true, None);
+        controller.set_progress(self.simulation.offset(), self.simulation.steps());
+        let Ok(gpu) = self.simulation.cells() else {
+            controller.set_validation("Failed to read the grid back".to_string());
+            return;
+        };
 
-            cpass.set_pipeline(&self.pipelines.compute);
-            cpass.set_push_constants(0, controller.compute_constants());
-            for (i, bind_group) in self.bind_groups.iter().enumerate() {
-                cpass.set_bind_group(i as u32, bind_group, &[]);
+        let mut emulated = cpu::step_tiled(&before, 0);
+        if (steps + tile::STEPS as u64) % electrics::RUN as u64 == 0 {
+            for _ in 0..electrics::STEPS_PER_RUN {
+                cpu::step_electrics(&mut emulated);
             }
-            cpass.dispatch_workgroups(workspace.0, workspace.1, workspace.2);
         }
-        ctx.queue.submit(Some(encoder.finish()));
+        let mut expected = before;
+        cpu::step_untiled(&mut expected, steps, tile::STEPS);
+        let gpu_mismatches = cpu::mismatches(&gpu, &expected);
+        let cpu_mismatches = cpu::mismatches(&emulated, &expected);
+        controller.set_validation(format!(
+            "{} sub-steps over {} cells: {}\nGPU tiled: {} differ\nCPU tiled: {} differ",
+            tile::STEPS,
+            expected.buffer.len(),
+            if gpu_mismatches == 0 && cpu_mismatches == 0 {
+                "passed"
+            } else {
+                "FAILED"
+            },
+            gpu_mismatches,
+            cpu_mismatches,
+        ));
     }
 
     pub fn render(
         &mut self,
         ctx: &GraphicsContext,
-        window: &Window,
-        ui: &mut Ui,
-        ui_state: &mut UiState,
+        ui_output: UiOutput,
         controller: &mut Controller,
     ) -> Result<(), wgpu::SurfaceError> {
         let output = match ctx.surface.get_current_texture() {
@@ -122,10 +252,13 @@ impl RenderPass {
             Err(err) => {
                 eprintln!("get_current_texture error: {err:?}");
                 return match err {
-                    wgpu::SurfaceError::Lost => {
+                    // The surface no longer matches the window, skip the frame
+                    wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated => {
                         ctx.surface.configure(&ctx.device, &ctx.config);
                         Ok(())
                     }
+                    // The next frame tries again
+                    wgpu::SurfaceError::Timeout => Ok(()),
                     _ => Err(err),
                 };
             }
@@ -134,20 +267,60 @@ impl RenderPass {
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
 
-        self.render_shader(ctx, &output_view, controller, window.inner_size());
-        self.render_ui(ctx, &output_view, window, ui, ui_state, controller);
+        self.bloom.resize(ctx, &self.sampled_bind_group_layout);
+        // Each encoder uploads its constants before the next one starts. The canvas sets
+        // them once for its compute pass and once per generated mip level.
+        let draws = self.canvas.levels().max(self.bloom.draws());
+        self.shader_constants.reserve(&ctx.device, draws);
+        if let Some(bits) = controller.painting() {
+            self.simulation.note_painted(bits);
+        }
+        self.render_canvas(ctx, controller);
+        self.render_shader(ctx, self.bloom.hdr_view(), controller);
+        self.render_bloom(ctx, &output_view, controller);
+        self.count(controller);
+        self.inspect(ctx, controller);
+        self.render_ui(ctx, &output_view, ui_output);
 
         output.present();
+        self.backup_grid(ctx);
 
         Ok(())
     }
 
+    /// Copies the grid back every `GRID_BACKUP_INTERVAL`. The copy is picked up on a
+    /// later frame, once it arrived, instead of waiting for the device.
+    fn backup_grid(&mut self, ctx: &GraphicsContext) {
+        // The browser cannot recover from a lost device
+        if cfg!(target_arch = "wasm32") {
+            return;
+        }
+        if let Some(pending) = &self.pending_backup {
+            match pending.try_take(&ctx.device) {
+                None => return,
+                Some(Ok(grid)) => self.grid_backup = Some(grid),
+                Some(Err(err)) => eprintln!("Failed to back up the grid: {err}"),
+            }
+            self.pending_backup = None;
+        }
+        if self.last_backup.elapsed() < GRID_BACKUP_INTERVAL {
+            return;
+        }
+        self.pending_backup = self.simulation.read_cells();
+        self.last_backup = Instant::now();
+    }
+
+    /// The shader modules and the latest grid backup, to recreate the render pass with
+    /// after the device was lost
+    pub fn into_recovery(self) -> (CompiledShaderModules, Option<Grid<Particle>>) {
+        (self.modules, self.grid_backup)
+    }
+
     fn render_shader(
-        &mut self,
+        &self,
         ctx: &GraphicsContext,
         output_view: &TextureView,
         controller: &Controller,
-        size: PhysicalSize<u32>,
     ) {
         let mut encoder = ctx
             .device
@@ -158,7 +331,9 @@ impl RenderPass {
             let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Shader Render Pass"),
                 occlusion_query_set: None,
-                timestamp_writes: None,
+                timestamp_writes: self
+                    .profiler()
+                    .map(|p| p.render_writes(Pass::Grid, false, true)),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                     view: output_view,
                     resolve_target: None,
@@ -169,44 +344,140 @@ impl RenderPass {
                 })],
                 depth_stencil_attachment: None,
             });
-            rpass.set_viewport(
-                0.0,
-                shared::UI_MENU_HEIGHT as f32,
-                (size.width - shared::UI_SIDEBAR_WIDTH) as f32,
-                (size.height - shared::UI_MENU_HEIGHT) as f32,
-                0.0,
-                1.0,
-            );
+            // Rounding to physical pixels can push the viewport just past the surface
+            let (offset, size) = controller.viewport();
+            let surface_size = glam::vec2(ctx.config.width as f32, ctx.config.height as f32);
+            let size = size.min(surface_size - offset);
+            if size.cmpgt(glam::Vec2::ZERO).all() {
+                rpass.set_viewport(offset.x, offset.y, size.x, size.y, 0.0, 1.0);
+
+                rpass.set_pipeline(&self.pipelines.render);
+                self.shader_constants.set_render(
+                    &mut rpass,
+                    wgpu::ShaderStages::FRAGMENT,
+                    1,
+                    controller.fragment_constants(),
+                );
+                rpass.set_bind_group(
+                    0,
+                    self.canvas.bind_group(controller.linear_filtering()),
+                    &[],
+                );
+                rpass.draw(0..3, 0..1);
+            }
+        }
 
-            rpass.set_pipeline(&self.pipelines.render);
-            rpass.set_push_constants(
-                wgpu::ShaderStages::FRAGMENT,
-                0,
-                controller.fragment_constants(),
-            );
-            for (i, bind_group) in self.bind_groups.iter().enumerate() {
-                rpass.set_bind_group(i as u32, bind_group, &[]);
+        self.shader_constants.upload(&ctx.queue);
+        ctx.queue.submit(Some(encoder.finish()));
+    }
+
+    /// Applies the brush and writes the colour of every cell into the canvas
+    fn render_canvas(&self, ctx: &GraphicsContext, controller: &Controller) {
+        let size = controller.grid_size();
+        let mut encoder = ctx
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Canvas Encoder"),
+            });
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Canvas Pass"),
+                timestamp_writes: self
+                    .profiler()
+                    .map(|p| p.compute_writes(Pass::Grid, true, false)),
+            });
+            self.shader_constants
+                .set_compute(&mut cpass, 2, controller.fragment_constants());
+            cpass.set_bind_group(0, self.simulation.grid_bind_group(), &[]);
+            cpass.set_bind_group(1, self.canvas.storage_bind_group(), &[]);
+            let automaton = controller.automaton() as usize;
+            for pipeline in [
+                &self.pipelines.paint[automaton],
+                &self.pipelines.color[automaton],
+            ] {
+                cpass.set_pipeline(pipeline);
+                cpass.dispatch_workgroups(size.x.div_ceil(16), size.y.div_ceil(16), 1);
             }
-            rpass.draw(0..3, 0..1);
         }
+        // Only needed once cells are smaller than a pixel
+        if controller.pixels_per_cell() < 1.0 {
+            self.canvas.generate_mips(
+                &mut encoder,
+                &self.pipelines.bloom.downsample,
+                &self.shader_constants,
+            );
+        }
+        self.shader_constants.upload(&ctx.queue);
+        ctx.queue.submit(Some(encoder.finish()));
+    }
 
+    fn render_bloom(
+        &self,
+        ctx: &GraphicsContext,
+        output_view: &TextureView,
+        controller: &Controller,
+    ) {
+        let mut encoder = ctx
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Bloom Encoder"),
+            });
+        self.bloom.render(
+            &mut encoder,
+            &self.pipelines.bloom,
+            output_view,
+            controller.bloom_strength(),
+            &self.shader_constants,
+        );
+        self.shader_constants.upload(&ctx.queue);
         ctx.queue.submit(Some(encoder.finish()));
     }
 
+    fn count(&mut self, controller: &mut Controller) {
+        if !controller.statistics().enabled() {
+            return;
+        }
+        match self.simulation.count() {
+            Some(Ok(counts)) => controller.statistics().push(counts),
+            Some(Err(err)) => eprintln!("Failed to read the counts back: {err}"),
+            None => {}
+        }
+    }
+
+    /// Shows the latest inspection that arrived while a new one is on its way, instead of
+    /// waiting for the device
+    fn inspect(&mut self, ctx: &GraphicsContext, controller: &mut Controller) {
+        let Some(cell) = controller.inspected_cell() else {
+            controller.set_inspection(None);
+            return;
+        };
+        match self.inspector.poll(ctx) {
+            Some(Ok(inspection)) => controller.set_inspection(Some(inspection)),
+            Some(Err(err)) => eprintln!("Failed to read the inspected cells back: {err}"),
+            None => {}
+        }
+        self.inspector.inspect(
+            ctx,
+            self.simulation.grid_buffer(),
+            controller.grid_size(),
+            cell,
+            controller.offset(),
+        );
+    }
+
     fn render_ui(
         &mut self,
         ctx: &GraphicsContext,
         output_view: &TextureView,
-        window: &Window,
-        ui: &mut Ui,
-        ui_state: &mut UiState,
-        controller: &mut Controller,
+        UiOutput {
+            clipped_primitives,
+            textures_delta,
+            pixels_per_point,
+        }: UiOutput,
     ) {
-        let (clipped_primitives, textures_delta) = ui.prepare(window, ui_state, controller);
-
         let screen_descriptor = egui_wgpu::ScreenDescriptor {
             size_in_pixels: [ctx.config.width, ctx.config.height],
-            pixels_per_point: window.scale_factor() as f32,
+            pixels_per_point,
         };
 
         for (id, delta) in &textures_delta.set {
@@ -229,10 +500,16 @@ impl RenderPass {
         );
 
         {
+            let profiling = self.profiling;
+            let timestamp_writes = self
+                .profiler
+                .as_ref()
+                .filter(|_| profiling)
+                .map(|p| p.render_writes(Pass::Ui, true, true));
             let rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("UI Render Pass"),
                 occlusion_query_set: None,
-                timestamp_writes: None,
+                timestamp_writes,
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                     view: output_view,
                     resolve_target: None,
@@ -258,83 +535,77 @@ impl RenderPass {
         ctx.queue.submit(Some(encoder.finish()));
     }
 
-    pub fn new_module(&mut self, ctx: &GraphicsContext, new_module: CompiledShaderModules) {
-        self.pipelines = create_pipeline(
-            &self.options,
+    /// Replaces the pipelines with ones created from `new_module`. The current pipelines
+    /// are kept if any of the new ones fails validation.
+    pub fn new_module(
+        &mut self,
+        ctx: &GraphicsContext,
+        new_module: CompiledShaderModules,
+    ) -> Result<(), String> {
+        ctx.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let pipelines = create_pipeline(
+            ctx.spirv_passthrough,
             &ctx.device,
             &self.pipeline_layouts,
             ctx.config.format,
-            new_module,
+            &new_module,
         );
+        let error = futures::executor::block_on(ctx.device.pop_error_scope());
+        let pipelines = pipelines?;
+        match error {
+            Some(err) => Err(err.to_string()),
+            None => {
+                self.simulation.new_module(&new_module)?;
+                self.pipelines = pipelines;
+                self.modules = new_module;
+                Ok(())
+            }
+        }
     }
 }
 
-fn maybe_create_bind_groups(
-    ctx: &GraphicsContext,
-    buffer_data: &BufferData,
-    bind_group_layouts: &Vec<BindGroupLayout>,
-) -> Vec<wgpu::BindGroup> {
-    buffer_data
-        .bind_group_buffers
-        .iter()
-        .zip(bind_group_layouts)
-        .enumerate()
-        .map(|(i, (buffer, layout))| {
-            let buffer = ctx.device.create_buffer_init(&match buffer {
-                BindGroupBufferType::SSBO(ssbo) => wgpu::util::BufferInitDescriptor {
-                    label: Some("Bind Group Buffer"),
-                    contents: ssbo.data,
-                    usage: wgpu::BufferUsages::STORAGE,
-                },
-                BindGroupBufferType::Uniform(uniform) => wgpu::util::BufferInitDescriptor {
-                    label: Some("Bind Group Buffer"),
-                    contents: uniform.data,
-                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-                },
-            });
-            ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: layout,
-                entries: &[wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: buffer.as_entire_binding(),
-                }],
-                label: Some(&format!("bind_group {}", i)),
-            })
-        })
-        .collect()
-}
-
 fn create_pipeline(
-    options: &Options,
+    spirv_passthrough: bool,
     device: &wgpu::Device,
     pipeline_layouts: &PipelineLayouts,
     surface_format: wgpu::TextureFormat,
-    compiled_shader_modules: CompiledShaderModules,
-) -> Pipelines {
-    // FIXME(eddyb) automate this decision by default.
-    let create_module = |module| {
-        if options.validate_spirv {
-            let wgpu::ShaderModuleDescriptorSpirV { label, source } = module;
-            device.create_shader_module(wgpu::ShaderModuleDescriptor {
-                label,
-                source: wgpu::ShaderSource::SpirV(source),
-            })
-        } else {
-            unsafe { device.create_shader_module_spirv(&module) }
-        }
-    };
+    compiled_shader_modules: &CompiledShaderModules,
+) -> Result<Pipelines, String> {
+    let create_module = |module| shader::create_module(device, spirv_passthrough, module);
 
     let vs_entry_point = shaders::main_vs;
     let fs_entry_point = shaders::main_fs;
-    let cs_entry_point = shaders::main_cs;
+    let bloom_entry_points = [
+        shaders::bloom_prefilter_fs,
+        shaders::bloom_downsample_fs,
+        shaders::bloom_upsample_fs,
+        shaders::composite_fs,
+    ];
 
-    let vs_module_descr = compiled_shader_modules.spv_module_for_entry_point(vs_entry_point);
-    let fs_module_descr = compiled_shader_modules.spv_module_for_entry_point(fs_entry_point);
-    let cs_module_descr = compiled_shader_modules.spv_module_for_entry_point(cs_entry_point);
+    let vs_module_descr = compiled_shader_modules.spv_module_for_entry_point(vs_entry_point)?;
+    let fs_module_descr = compiled_shader_modules.spv_module_for_entry_point(fs_entry_point)?;
 
     // HACK(eddyb) avoid calling `device.create_shader_module` twice unnecessarily.
     let vs_fs_same_module = std::ptr::eq(&vs_module_descr.source[..], &fs_module_descr.source[..]);
-    let vs_cs_same_module = std::ptr::eq(&vs_module_descr.source[..], &cs_module_descr.source[..]);
+    let separate_module = |entry_point| -> Result<_, String> {
+        let module_descr = compiled_shader_modules.spv_module_for_entry_point(entry_point)?;
+        let same_module = std::ptr::eq(&vs_module_descr.source[..], &module_descr.source[..]);
+        Ok((!same_module).then(|| create_module(module_descr)))
+    };
+    let bloom_modules = bloom_entry_points
+        .into_iter()
+        .map(separate_module)
+        .collect::<Result<Vec<_>, _>>()?;
+    let canvas_modules = |entry_point: fn(&dyn Rules) -> &'static str| {
+        AutomatonKind::iter()
+            .map(|automaton| {
+                let entry_point = entry_point(automaton.rules());
+                Ok((entry_point, separate_module(entry_point)?))
+            })
+            .collect::<Result<Vec<_>, String>>()
+    };
+    let paint_modules = canvas_modules(|rules| rules.paint_entry_point())?;
+    let color_modules = canvas_modules(|rules| rules.color_entry_point())?;
 
     let vs_module = &create_module(vs_module_descr);
     let fs_module;
@@ -344,13 +615,6 @@ fn create_pipeline(
         fs_module = create_module(fs_module_descr);
         &fs_module
     };
-    let cs_module;
-    let cs_module = if vs_cs_same_module {
-        vs_module
-    } else {
-        cs_module = create_module(cs_module_descr);
-        &cs_module
-    };
 
     let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: None,
@@ -380,7 +644,7 @@ fn create_pipeline(
             module: fs_module,
             entry_point: fs_entry_point,
             targets: &[Some(wgpu::ColorTargetState {
-                format: surface_format,
+                format: crate::bloom::HDR_FORMAT,
                 blend: None,
                 write_mask: wgpu::ColorWrites::ALL,
             })],
@@ -389,73 +653,107 @@ fn create_pipeline(
         multiview: None,
         cache: None,
     });
-    let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-        label: None,
-        layout: Some(&pipeline_layouts.compute),
-        module: &cs_module,
-        entry_point: cs_entry_point,
-        compilation_options: Default::default(),
-        cache: None,
-    });
-    Pipelines {
-        render: render_pipeline,
-        compute: compute_pipeline,
-    }
-}
-
-fn create_bind_group_layouts(
-    ctx: &GraphicsContext,
-    buffer_data: &BufferData,
-) -> Vec<BindGroupLayout> {
-    buffer_data
-        .bind_group_buffers
-        .iter()
-        .enumerate()
-        .map(|(i, buffer)| {
-            ctx.device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    entries: &[wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: (match buffer {
-                                BindGroupBufferType::Uniform(_) => wgpu::BufferBindingType::Uniform,
-                                BindGroupBufferType::SSBO(SSBO { read_only, .. }) => {
-                                    wgpu::BufferBindingType::Storage {
-                                        read_only: *read_only,
-                                    }
-                                }
-                            }),
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    }],
-                    label: Some(&format!("bind_group_layout {}", i)),
-                })
+    let create_canvas_pipeline = |(entry_point, module): &(&str, Option<wgpu::ShaderModule>)| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(&pipeline_layouts.canvas),
+            module: module.as_ref().unwrap_or(vs_module),
+            entry_point,
+            compilation_options: Default::default(),
+            cache: None,
         })
-        .collect()
+    };
+    let create_bloom_pipeline = |i: usize, layout, format, blend| {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(bloom_entry_points[i]),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: vs_module,
+                entry_point: vs_entry_point,
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: bloom_modules[i].as_ref().unwrap_or(vs_module),
+                entry_point: bloom_entry_points[i],
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            multiview: None,
+            cache: None,
+        })
+    };
+    let additive = wgpu::BlendState {
+        color: wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        },
+        alpha: wgpu::BlendComponent::REPLACE,
+    };
+    let hdr_format = crate::bloom::HDR_FORMAT;
+    let bloom = BloomPipelines {
+        prefilter: create_bloom_pipeline(0, &pipeline_layouts.bloom, hdr_format, None),
+        downsample: create_bloom_pipeline(1, &pipeline_layouts.bloom, hdr_format, None),
+        upsample: create_bloom_pipeline(2, &pipeline_layouts.bloom, hdr_format, Some(additive)),
+        composite: create_bloom_pipeline(3, &pipeline_layouts.composite, surface_format, None),
+    };
+    Ok(Pipelines {
+        render: render_pipeline,
+        paint: paint_modules.iter().map(create_canvas_pipeline).collect(),
+        color: color_modules.iter().map(create_canvas_pipeline).collect(),
+        bloom,
+    })
 }
 
 fn create_pipeline_layouts(
     ctx: &GraphicsContext,
-    bind_group_layouts: &[BindGroupLayout],
+    grid_bind_group_layout: &BindGroupLayout,
+    sampled_bind_group_layout: &BindGroupLayout,
+    canvas_bind_group_layout: &BindGroupLayout,
+    shader_constants: &ShaderConstants,
 ) -> PipelineLayouts {
-    let bind_group_layouts = &bind_group_layouts.iter().collect::<Vec<_>>();
-    let create = |stages, mem_size| {
+    let create_with = |bind_group_layouts: &[&BindGroupLayout], stages, mem_size| {
+        let bind_group_layouts = bind_group_layouts
+            .iter()
+            .copied()
+            .chain(shader_constants.bind_group_layout())
+            .collect::<Vec<_>>();
         ctx.device
             .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: None,
-                bind_group_layouts,
-                push_constant_ranges: &[wgpu::PushConstantRange {
-                    stages,
-                    range: 0..mem_size as u32,
-                }],
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &shader_constants.push_constant_ranges(stages, mem_size),
             })
     };
     use shared::push_constants::sandsim::*;
     PipelineLayouts {
-        render: create(wgpu::ShaderStages::FRAGMENT, FragmentConstants::mem_size()),
-        compute: create(wgpu::ShaderStages::COMPUTE, ComputeConstants::mem_size()),
+        render: create_with(
+            &[sampled_bind_group_layout],
+            wgpu::ShaderStages::FRAGMENT,
+            FragmentConstants::mem_size(),
+        ),
+        canvas: create_with(
+            &[grid_bind_group_layout, canvas_bind_group_layout],
+            wgpu::ShaderStages::COMPUTE,
+            FragmentConstants::mem_size(),
+        ),
+        bloom: create_with(
+            &[sampled_bind_group_layout],
+            wgpu::ShaderStages::FRAGMENT,
+            BloomConstants::mem_size(),
+        ),
+        composite: create_with(
+            &[sampled_bind_group_layout, sampled_bind_group_layout],
+            wgpu::ShaderStages::FRAGMENT,
+            BloomConstants::mem_size(),
+        ),
     }
 }
@@ -0,0 +1,68 @@
+//! Scene files for `--scene`. Each line is a row of cells from the top down, one
+//! character per cell: `.` or a space for empty, `s` for sand, `w` for water, `c` for
+//! conductor, `h` for electron head and `t` for electron tail. Short lines are padded with
+//! empty cells. The scene is placed at the bottom left of the world.
+
+use glam::*;
+use shared::grid::Grid;
+use shared::particle::{Particle, ParticleType};
+
+#[derive(Clone)]
+pub struct Scene {
+    rows: Vec<Vec<ParticleType>>,
+    width: usize,
+}
+
+impl Scene {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|err| format!("Failed to read {path}: {err}"))?;
+        Self::parse(&text)
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let rows = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                line.chars()
+                    .map(|c| {
+                        material(c).ok_or_else(|| format!("Unknown cell {c:?} on line {}", i + 1))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if width == 0 {
+            return Err("The scene is empty".to_string());
+        }
+        Ok(Self { rows, width })
+    }
+
+    /// Size in cells
+    pub fn size(&self) -> UVec2 {
+        uvec2(self.width as u32, self.rows.len() as u32)
+    }
+
+    /// Fills the bottom left of `grid`, which has to be at least as large as the scene
+    pub fn place(&self, grid: &mut Grid<Particle>) {
+        let top = grid.h - self.rows.len();
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, &particle_type) in row.iter().enumerate() {
+                grid.set(x, top + y, Particle::new(particle_type));
+            }
+        }
+    }
+}
+
+fn material(c: char) -> Option<ParticleType> {
+    match c {
+        '.' | ' ' => Some(ParticleType::Empty),
+        's' => Some(ParticleType::Sand),
+        'w' => Some(ParticleType::Water),
+        'c' => Some(ParticleType::Conductor),
+        'h' => Some(ParticleType::ElectronHead),
+        't' => Some(ParticleType::ElectronTail),
+        _ => None,
+    }
+}
@@ -0,0 +1,80 @@
+use crate::bindings::Tool;
+use serde::{Deserialize, Serialize};
+use shared::particle::ParticleType;
+
+const FILE_NAME: &str = "settings.toml";
+
+/// User settings that persist between launches, stored in `settings.toml` in the
+/// config directory
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub ui: UiSettings,
+    pub controller: ControllerSettings,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiSettings {
+    pub show_fps: bool,
+    /// Time the passes on the GPU and show the times next to the FPS counter
+    pub show_gpu_times: bool,
+    pub vsync: bool,
+    pub show_sidebar: bool,
+}
+
+impl Default for UiSettings {
+    fn default() -> Self {
+        Self {
+            show_fps: true,
+            show_gpu_times: false,
+            vsync: true,
+            show_sidebar: true,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControllerSettings {
+    pub brush_size: f32,
+    /// Simulation speed multiplier, as shown in the sidebar
+    pub speed: f32,
+    pub primary_material: ParticleType,
+    pub secondary_material: ParticleType,
+    pub tool: Tool,
+    pub zoom: f32,
+    /// Size of a cell in physical pixels at zoom 1
+    pub cell_size: f32,
+    /// Smooth cells when magnified instead of drawing them as squares
+    pub linear_filtering: bool,
+    pub bloom_strength: f32,
+    pub debug: bool,
+}
+
+impl Default for ControllerSettings {
+    fn default() -> Self {
+        Self {
+            brush_size: 20.0,
+            speed: 1.0,
+            primary_material: ParticleType::Sand,
+            secondary_material: ParticleType::Empty,
+            tool: Tool::Brush,
+            zoom: 1.0,
+            cell_size: 1.0,
+            linear_filtering: false,
+            bloom_strength: 0.5,
+            debug: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        crate::config::load(FILE_NAME)
+    }
+
+    pub fn save(&self) {
+        crate::config::save(FILE_NAME, self);
+    }
+}
@@ -1,108 +1,187 @@
+#[cfg(hot_reload)]
 use crate::Options;
 use std::borrow::Cow;
 
+/// Names of the entry points, as passed to the pipelines. Written by the builder along
+/// with the precompiled shaders, also with hot reloading.
+pub mod entry_points {
+    include!(concat!(env!("OUT_DIR"), "/entry_points.rs"));
+}
+
+/// Creates the module described by `module`, handing it to the driver as is with
+/// `spirv_passthrough`
+pub fn create_module(
+    device: &wgpu::Device,
+    spirv_passthrough: bool,
+    module: wgpu::ShaderModuleDescriptorSpirV,
+) -> wgpu::ShaderModule {
+    // FIXME(eddyb) automate this decision by default.
+    if spirv_passthrough {
+        unsafe { device.create_shader_module_spirv(&module) }
+    } else {
+        let wgpu::ShaderModuleDescriptorSpirV { label, source } = module;
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label,
+            source: wgpu::ShaderSource::SpirV(source),
+        })
+    }
+}
+
 pub struct CompiledShaderModules {
     named_spv_modules: Vec<(Option<String>, wgpu::ShaderModuleDescriptorSpirV<'static>)>,
+    /// Whether the constants are push constants, or read from a buffer with the
+    /// `constant-buffer` feature
+    push_constants: bool,
 }
 
 impl CompiledShaderModules {
+    pub fn push_constants(&self) -> bool {
+        self.push_constants
+    }
+
+    /// The module containing `wanted_entry`. Reloaded shaders can lack an entry point
+    /// the runner needs, such as after renaming it.
     pub fn spv_module_for_entry_point<'a>(
         &'a self,
         wanted_entry: &str,
-    ) -> wgpu::ShaderModuleDescriptorSpirV<'a> {
+    ) -> Result<wgpu::ShaderModuleDescriptorSpirV<'a>, String> {
         for (name, spv_module) in &self.named_spv_modules {
             match name {
                 Some(name) if name != wanted_entry => continue,
                 _ => {
-                    return wgpu::ShaderModuleDescriptorSpirV {
+                    return Ok(wgpu::ShaderModuleDescriptorSpirV {
                         label: name.as_deref(),
                         source: Cow::Borrowed(&spv_module.source),
-                    };
+                    });
                 }
             }
         }
-        unreachable!(
-            "{wanted_entry:?} not found in modules {:?}",
-            self.named_spv_modules
-                .iter()
-                .map(|(name, _)| name)
-                .collect::<Vec<_>>()
-        );
+        let names: Vec<_> = self
+            .named_spv_modules
+            .iter()
+            .flat_map(|(name, _)| name)
+            .collect();
+        Err(format!(
+            "Entry point {wanted_entry:?} not found in the shader modules {names:?}"
+        ))
+    }
+}
+
+/// Shaders compiled by the build script, in the variant for `push_constants`
+pub fn precompiled(push_constants: bool) -> CompiledShaderModules {
+    let module = if push_constants {
+        wgpu::include_spirv_raw!(concat!(env!("OUT_DIR"), "/sandsim.spv"))
+    } else {
+        wgpu::include_spirv_raw!(concat!(env!("OUT_DIR"), "/sandsim-constant-buffer.spv"))
+    };
+    CompiledShaderModules {
+        named_spv_modules: vec![(None, module)],
+        push_constants,
     }
 }
 
-pub fn maybe_watch(
-    options: &Options,
-    #[cfg(not(target_arch = "wasm32"))] on_watch: Option<
-        Box<dyn FnMut(CompiledShaderModules) + Send + 'static>,
-    >,
-) -> CompiledShaderModules {
-    #[cfg(not(target_arch = "wasm32"))]
-    {
-        use spirv_builder::{CompileResult, MetadataPrintout, SpirvBuilder};
-        use std::path::PathBuf;
-        // Hack: spirv_builder builds into a custom directory if running under cargo, to not
-        // deadlock, and the default target directory if not. However, packages like `proc-macro2`
-        // have different configurations when being built here vs. when building
-        // rustc_codegen_spirv normally, so we *want* to build into a separate target directory, to
-        // not have to rebuild half the crate graph every time we run. So, pretend we're running
-        // under cargo by setting these environment variables.
-        std::env::set_var(
-            "OUT_DIR",
-            option_env!("SHADERS_TARGET_DIR").unwrap_or(env!("OUT_DIR")),
+/// Called with the modules or the error of every rebuild of the watched shaders
+#[cfg(hot_reload)]
+pub type OnWatch = Box<dyn FnMut(Result<CompiledShaderModules, String>) + Send + 'static>;
+
+/// Passed the builds of every watcher started by `watch` and `rebuild`
+#[cfg(hot_reload)]
+static ON_WATCH: std::sync::Mutex<Option<OnWatch>> = std::sync::Mutex::new(None);
+
+/// Watches the shader sources in the background with `SpirvBuilder::watch`, building
+/// them in the variant for `push_constants` once and then on every change. Every build
+/// or its error is passed to `on_watch`. The watcher only reports successful rebuilds,
+/// the compiler prints the errors of the others to the terminal.
+#[cfg(hot_reload)]
+pub fn watch(options: &Options, push_constants: bool, on_watch: OnWatch) {
+    *ON_WATCH.lock().unwrap() = Some(on_watch);
+    rebuild(options, push_constants);
+}
+
+/// Starts another watcher building the shaders in the variant for `push_constants`,
+/// once the adapter turns out to differ from the one the first watcher was started for.
+/// The earlier watcher keeps running, the modules it builds are in the other variant.
+#[cfg(hot_reload)]
+pub fn rebuild(options: &Options, push_constants: bool) {
+    let builder = builder(options.validate_spirv, push_constants);
+    std::thread::spawn(move || {
+        let send = |result: Result<CompiledShaderModules, String>| {
+            if let Some(on_watch) = ON_WATCH.lock().unwrap().as_mut() {
+                on_watch(result);
+            }
+        };
+        // Returns once the first build succeeded, or fails on the configuration
+        let first = builder.watch(move |result| send(load(result, push_constants)));
+        send(
+            first
+                .map_err(|err| err.to_string())
+                .and_then(|result| load(result, push_constants)),
         );
-        std::env::set_var("PROFILE", env!("PROFILE"));
-        let manifest_dir = option_env!("SHADERS_DIR").unwrap_or(env!("CARGO_MANIFEST_DIR"));
-        let crate_path = [manifest_dir, "..", "shaders", "sandsim"]
-            .iter()
-            .copied()
-            .collect::<PathBuf>();
+    });
+}
 
-        let has_debug_printf = !options.validate_spirv;
+/// Configures `spirv-builder` for the shaders, in the variant for `push_constants`
+#[cfg(hot_reload)]
+fn builder(validate_spirv: bool, push_constants: bool) -> spirv_builder::SpirvBuilder {
+    use spirv_builder::{MetadataPrintout, ShaderPanicStrategy, SpirvBuilder};
+    let manifest_dir = option_env!("SHADERS_DIR").unwrap_or(env!("CARGO_MANIFEST_DIR"));
+    let crate_path = [manifest_dir, "..", "shaders", "sandsim"]
+        .iter()
+        .copied()
+        .collect::<std::path::PathBuf>();
 
-        let builder = SpirvBuilder::new(crate_path, "spirv-unknown-vulkan1.1")
-            .print_metadata(MetadataPrintout::None)
-            .shader_panic_strategy(if has_debug_printf {
-                spirv_builder::ShaderPanicStrategy::DebugPrintfThenExit {
-                    print_inputs: true,
-                    print_backtrace: true,
-                }
-            } else {
-                spirv_builder::ShaderPanicStrategy::SilentExit
-            })
-            // HACK(eddyb) needed because of `debugPrintf` instrumentation limitations
-            // (see https://github.com/KhronosGroup/SPIRV-Tools/issues/4892).
-            .multimodule(has_debug_printf);
-        let initial_result = if let Some(mut f) = on_watch {
-            builder
-                .watch(move |compile_result| f(handle_compile_result(compile_result)))
-                .expect("Configuration is correct for watching")
+    let has_debug_printf = !validate_spirv;
+    let features = if push_constants {
+        vec![]
+    } else {
+        vec!["constant-buffer".to_string()]
+    };
+    SpirvBuilder::new(crate_path, "spirv-unknown-vulkan1.1")
+        .print_metadata(MetadataPrintout::None)
+        .shader_crate_features(features)
+        .shader_panic_strategy(if has_debug_printf {
+            ShaderPanicStrategy::DebugPrintfThenExit {
+                print_inputs: true,
+                print_backtrace: true,
+            }
         } else {
-            builder.build().unwrap()
-        };
-        fn handle_compile_result(compile_result: CompileResult) -> CompiledShaderModules {
-            let load_spv_module = |path| {
-                let data = std::fs::read(path).unwrap();
-                // FIXME(eddyb) this reallocates all the data pointlessly, there is
-                // not a good reason to use `ShaderModuleDescriptorSpirV` specifically.
-                let spirv = Cow::Owned(wgpu::util::make_spirv_raw(&data).into_owned());
-                wgpu::ShaderModuleDescriptorSpirV {
-                    label: None,
-                    source: spirv,
-                }
+            ShaderPanicStrategy::SilentExit
+        })
+        // HACK(eddyb) needed because of `debugPrintf` instrumentation limitations
+        // (see https://github.com/KhronosGroup/SPIRV-Tools/issues/4892).
+        .multimodule(has_debug_printf)
+}
+
+/// Reads the modules of `result`, named by their entry point
+#[cfg(hot_reload)]
+fn load(
+    result: spirv_builder::CompileResult,
+    push_constants: bool,
+) -> Result<CompiledShaderModules, String> {
+    let paths = match result.module {
+        spirv_builder::ModuleResult::SingleModule(path) => vec![(None, path)],
+        spirv_builder::ModuleResult::MultiModule(modules) => modules
+            .into_iter()
+            .map(|(name, path)| (Some(name), path))
+            .collect(),
+    };
+    let named_spv_modules = paths
+        .into_iter()
+        .map(|(name, path)| {
+            let data = std::fs::read(&path)
+                .map_err(|err| format!("Failed to read {}: {err}", path.display()))?;
+            // FIXME(eddyb) this reallocates all the data pointlessly, there is
+            // not a good reason to use `ShaderModuleDescriptorSpirV` specifically.
+            let spirv = Cow::Owned(wgpu::util::make_spirv_raw(&data).into_owned());
+            let module = wgpu::ShaderModuleDescriptorSpirV {
+                label: None,
+                source: spirv,
             };
-            CompiledShaderModules {
-                named_spv_modules: match compile_result.module {
-                    spirv_builder::ModuleResult::SingleModule(path) => {
-                        vec![(None, load_spv_module(path))]
-                    }
-                    spirv_builder::ModuleResult::MultiModule(modules) => modules
-                        .into_iter()
-                        .map(|(name, path)| (Some(name), load_spv_module(path)))
-                        .collect(),
-                },
-            }
-        }
-        handle_compile_result(initial_result)
-    }
+            Ok((name, module))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(CompiledShaderModules {
+        named_spv_modules,
+        push_constants,
+    })
 }
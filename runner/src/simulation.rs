@@ -0,0 +1,1036 @@
+//! The falling sand simulation on its own, without a window or a UI. It steps the grid
+//! on a wgpu device with the same shaders as the runner, or on the CPU, and can be
+//! embedded in other windows or test harnesses. The grid can run the other automata of
+//! `AutomatonKind` as well.
+//!
+//! ```no_run
+//! use runner::simulation::Simulation;
+//! use shared::{grid::Grid, particle::ParticleType};
+//!
+//! let mut sim = Simulation::on_cpu(Grid::new(64, 64));
+//! sim.paint(glam::uvec2(32, 8), 4, ParticleType::Sand);
+//! sim.step(100);
+//! sim.save("sand.grid").unwrap();
+//! ```
+
+use crate::{
+    automaton::{AutomatonKind, Dispatch},
+    constants::ShaderConstants,
+    cpu,
+    readback::Ring,
+    shader::{self, entry_points, CompiledShaderModules},
+};
+use bytemuck::Zeroable;
+use glam::*;
+use shared::{
+    automata::Wireworld,
+    electrics,
+    grid::Grid,
+    particle::{Particle, ParticleType},
+    push_constants::sandsim::ComputeConstants,
+    tile,
+};
+use std::{
+    io,
+    path::Path,
+    sync::{mpsc, Arc},
+};
+use wgpu::{util::DeviceExt, BindGroupLayout};
+
+const PARTICLE_SIZE: u64 = std::mem::size_of::<Particle>() as u64;
+/// Start of a saved grid, which is followed by its `AutomatonKind`, its width, its height
+/// and the bits of its cells, all as little endian `u32`s
+const MAGIC: &[u8; 8] = b"SANDGRID";
+const COUNTS_SIZE: u64 = (ParticleType::COUNT * std::mem::size_of::<u32>()) as u64;
+/// Counts on their way back at once, enough to start one every frame
+const COUNT_STAGING_BUFFERS: usize = 3;
+
+/// Cells on their way back from the GPU, see `Simulation::read_cells`
+pub struct PendingCells {
+    buffer: wgpu::Buffer,
+    size: UVec2,
+    mapped: mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>,
+}
+
+impl PendingCells {
+    /// The cells once they arrived. This polls `device` without waiting for it.
+    pub fn try_take(
+        &self,
+        device: &wgpu::Device,
+    ) -> Option<Result<Grid<Particle>, wgpu::BufferAsyncError>> {
+        device.poll(wgpu::Maintain::Poll);
+        let mapped = self.mapped.try_recv().ok()?;
+        Some(mapped.map(|()| {
+            let cells = bytemuck::cast_slice(&self.buffer.slice(..).get_mapped_range()).to_vec();
+            self.buffer.unmap();
+            Grid {
+                w: self.size.x as usize,
+                h: self.size.y as usize,
+                buffer: cells,
+            }
+        }))
+    }
+}
+
+pub struct Simulation {
+    size: UVec2,
+    /// Sub-steps run so far, which also give the offset of the Margolus blocks
+    steps: u64,
+    /// Use the tiled compute shader for runs of `tile::STEPS` sub-steps
+    tiled: bool,
+    /// Whether the grid may hold cells the electronics step, see `has_electronics`.
+    /// Their dispatches are skipped otherwise.
+    electronics: bool,
+    automaton: AutomatonKind,
+    backend: Backend,
+}
+
+enum Backend {
+    Cpu(Grid<Particle>),
+    Gpu(Box<Gpu>),
+}
+
+struct Gpu {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    #[cfg(feature = "app")]
+    spirv_passthrough: bool,
+    /// Layout of the grid at set 0, the counts of every material at set 1 and the
+    /// scratch grid at set 2, where the compute shaders expect them
+    storage_layout: BindGroupLayout,
+    grids: Grids,
+    counts: wgpu::Buffer,
+    counts_bind_group: wgpu::BindGroup,
+    /// Kept with `spirv_passthrough` for recreating the pipelines
+    #[cfg(feature = "app")]
+    pipeline_layout: wgpu::PipelineLayout,
+    pipelines: Pipelines,
+    shader_constants: ShaderConstants,
+    /// Readback of the counts of every material
+    counter: Ring<()>,
+}
+
+/// The cells and a scratch grid of the same size. Shaders that write every cell at once
+/// read the grid at set 0 and write the scratch grid at set 2, which then takes over as
+/// the grid instead of being copied back.
+struct Grids {
+    buffers: [wgpu::Buffer; 2],
+    bind_groups: [wgpu::BindGroup; 2],
+    /// Index of the buffer holding the cells
+    current: usize,
+}
+
+struct Pipelines {
+    /// Step of every automaton, indexed by `AutomatonKind`
+    steps: Vec<wgpu::ComputePipeline>,
+    tiled: wgpu::ComputePipeline,
+    count: wgpu::ComputePipeline,
+    electrics: wgpu::ComputePipeline,
+}
+
+impl Simulation {
+    /// Steps `grid` on the CPU, with the same results as the shaders
+    pub fn on_cpu(grid: Grid<Particle>) -> Self {
+        Self::new(
+            grid_size(&grid),
+            has_electronics(&grid),
+            AutomatonKind::Sand,
+            Backend::Cpu(grid),
+        )
+    }
+
+    /// Steps `grid` on `device`, with the shaders embedded by the build script. The
+    /// constants are push constants and the shaders are passed through to the driver if
+    /// the device was created with those features. Fails if the grid exceeds the limits
+    /// of the device.
+    pub fn on_gpu(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        grid: &Grid<Particle>,
+    ) -> io::Result<Self> {
+        let features = device.features();
+        let push_constants = features.contains(wgpu::Features::PUSH_CONSTANTS)
+            && device.limits().max_push_constant_size >= crate::constants::MAX_SIZE as u32;
+        let spirv_passthrough = features.contains(wgpu::Features::SPIRV_SHADER_PASSTHROUGH);
+        let modules = shader::precompiled(push_constants);
+        Self::with_modules(
+            device,
+            queue,
+            &modules,
+            spirv_passthrough,
+            AutomatonKind::Sand,
+            grid,
+        )
+    }
+
+    /// Steps `grid`, whose cells are states of `automaton`, on `device` with the pipelines
+    /// created from `modules`. Fails if the grid exceeds the limits of the device, or if
+    /// the modules lack an entry point.
+    pub(crate) fn with_modules(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        modules: &CompiledShaderModules,
+        spirv_passthrough: bool,
+        automaton: AutomatonKind,
+        grid: &Grid<Particle>,
+    ) -> io::Result<Self> {
+        check_limits(&device, grid_size(grid))?;
+        let storage_layout = create_storage_bind_group_layout(&device, "Storage");
+        let shader_constants = ShaderConstants::new(&device, modules.push_constants());
+        let pipeline_layout = {
+            let bind_group_layouts = [&storage_layout; 3]
+                .into_iter()
+                .chain(shader_constants.bind_group_layout())
+                .collect::<Vec<_>>();
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Simulation Pipeline Layout"),
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &shader_constants.push_constant_ranges(
+                    wgpu::ShaderStages::COMPUTE,
+                    ComputeConstants::mem_size(),
+                ),
+            })
+        };
+        let pipelines = create_pipelines(&device, &pipeline_layout, modules, spirv_passthrough)
+            .map_err(io::Error::other)?;
+        let grids = Grids::new(&device, &storage_layout, grid);
+        let counts = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Counts Buffer"),
+            contents: bytemuck::cast_slice(&[0u32; ParticleType::COUNT]),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+        });
+        let counts_bind_group = create_bind_group(&device, &storage_layout, "Counts", &counts);
+        let counter = Ring::new(
+            &device,
+            "Counter Buffer",
+            COUNTS_SIZE,
+            COUNT_STAGING_BUFFERS,
+        );
+        Ok(Self::new(
+            grid_size(grid),
+            has_electronics(grid),
+            automaton,
+            Backend::Gpu(Box::new(Gpu {
+                device,
+                queue,
+                #[cfg(feature = "app")]
+                spirv_passthrough,
+                storage_layout,
+                grids,
+                counts,
+                counts_bind_group,
+                #[cfg(feature = "app")]
+                pipeline_layout,
+                pipelines,
+                shader_constants,
+                counter,
+            })),
+        ))
+    }
+
+    fn new(size: UVec2, electronics: bool, automaton: AutomatonKind, backend: Backend) -> Self {
+        Self {
+            size,
+            steps: 0,
+            tiled: false,
+            electronics,
+            automaton,
+            backend,
+        }
+    }
+
+    /// Size of the grid in cells
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    /// Offset of the Margolus blocks of the next sub-step, 0 or 1
+    pub fn offset(&self) -> u32 {
+        (self.steps % 2) as u32
+    }
+
+    /// Number of sub-steps run so far
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Whether runs of `tile::STEPS` sub-steps, aligned to multiples of it, are stepped
+    /// by the tiled shader. The result is the same, only faster on large grids.
+    pub fn set_tiled(&mut self, tiled: bool) {
+        self.tiled = tiled;
+    }
+
+    pub fn automaton(&self) -> AutomatonKind {
+        self.automaton
+    }
+
+    /// Selects the automaton the cells are stepped and counted as, and clears the cells
+    /// to its background. The bits of one automaton mean nothing to another, so this is
+    /// usually followed by `set_cells`.
+    pub fn set_automaton(&mut self, automaton: AutomatonKind) {
+        self.automaton = automaton;
+        let grid = automaton.new_grid(self.size.x as usize, self.size.y as usize, false);
+        self.write_cells(&grid);
+    }
+
+    /// Runs `sub_steps` sub-steps. Margolus sub-steps alternate the offset between them.
+    pub fn step(&mut self, sub_steps: u32) {
+        self.step_with(sub_steps, self.tiled, None);
+    }
+
+    /// Runs `sub_steps` sub-steps. On the GPU they are recorded into a single compute
+    /// pass, which writes `timestamp_writes`.
+    pub(crate) fn step_with(
+        &mut self,
+        sub_steps: u32,
+        tiled: bool,
+        timestamp_writes: Option<wgpu::ComputePassTimestampWrites>,
+    ) {
+        if sub_steps == 0 {
+            return;
+        }
+        let rules = self.automaton.rules();
+        let mut schedule = rules.schedule(self.steps, sub_steps, tiled);
+        if !self.electronics {
+            schedule.retain(|&dispatch| dispatch != Dispatch::Electrics);
+        }
+        match &mut self.backend {
+            Backend::Cpu(grid) => {
+                for dispatch in schedule {
+                    match dispatch {
+                        Dispatch::Blocks { offset } => rules.step_cpu(grid, offset),
+                        Dispatch::Cells => rules.step_cpu(grid, 0),
+                        Dispatch::Tiled { offset } => *grid = cpu::step_tiled(grid, offset),
+                        Dispatch::Electrics => cpu::step_electrics(grid),
+                    }
+                }
+            }
+            Backend::Gpu(gpu) => {
+                gpu.shader_constants
+                    .reserve(&gpu.device, schedule.len() as u32);
+                let mut encoder = gpu
+                    .device
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+                gpu.record(
+                    &mut encoder,
+                    self.automaton,
+                    self.size,
+                    &schedule,
+                    timestamp_writes,
+                );
+                gpu.shader_constants.upload(&gpu.queue);
+                gpu.queue.submit(Some(encoder.finish()));
+            }
+        }
+        self.steps += sub_steps as u64;
+    }
+
+    /// Fills the cells within `radius` of `center` with new particles of `material`
+    pub fn paint(&mut self, center: UVec2, radius: u32, material: ParticleType) {
+        self.note_painted(material as u32);
+        self.paint_with(center, radius, || Particle::new(material));
+    }
+
+    /// Fills the cells within `radius` of `center` with the state of another automaton,
+    /// see `Rules::states`. Fails if `bits` is no valid cell of the current automaton.
+    pub fn paint_state(&mut self, center: UVec2, radius: u32, bits: u32) -> io::Result<()> {
+        if !self.automaton.rules().valid(bits) {
+            let error = format!("{bits:#x} is no cell of {}", self.automaton.name());
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, error));
+        }
+        self.note_painted(bits);
+        self.paint_with(center, radius, || Particle::from_bits(bits));
+        Ok(())
+    }
+
+    /// Tells the simulation that cells with `bits` were painted other than by `paint`,
+    /// such as by the canvas pass of the runner, so that it steps their electronics
+    pub fn note_painted(&mut self, bits: u32) {
+        self.electronics |= conducts(Particle::from_bits(bits));
+    }
+
+    fn paint_with(&mut self, center: UVec2, radius: u32, cell: impl Fn() -> Particle) {
+        let center = center.as_ivec2();
+        let radius = radius as i32;
+        let size = self.size.as_ivec2();
+        for y in (center.y - radius).max(0)..(center.y + radius + 1).min(size.y) {
+            let dy = y - center.y;
+            let half_width = (((radius * radius - dy * dy) as f32).sqrt()) as i32;
+            let start = (center.x - half_width).max(0);
+            let end = (center.x + half_width + 1).min(size.x);
+            if start >= end {
+                continue;
+            }
+            let row: Vec<_> = (start..end).map(|_| cell()).collect();
+            match &mut self.backend {
+                Backend::Cpu(grid) => {
+                    let i = (y * size.x + start) as usize;
+                    grid.buffer[i..i + row.len()].copy_from_slice(&row);
+                }
+                Backend::Gpu(gpu) => gpu.queue.write_buffer(
+                    gpu.grids.buffer(),
+                    (y * size.x + start) as u64 * PARTICLE_SIZE,
+                    bytemuck::cast_slice(&row),
+                ),
+            }
+        }
+    }
+
+    /// A copy of every cell. On the GPU this waits for the device, which the browser
+    /// does not allow, and fails if the copy cannot be mapped.
+    pub fn cells(&self) -> Result<Grid<Particle>, wgpu::BufferAsyncError> {
+        match &self.backend {
+            Backend::Cpu(grid) => Ok(Grid {
+                w: grid.w,
+                h: grid.h,
+                buffer: grid.buffer.clone(),
+            }),
+            Backend::Gpu(gpu) => {
+                let buffer = copy_grid(&gpu.device, &gpu.queue, gpu.grids.buffer());
+                let slice = buffer.slice(..);
+                crate::readback::map_blocking(&gpu.device, slice)?;
+                let cells = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+                Ok(Grid {
+                    w: self.size.x as usize,
+                    h: self.size.y as usize,
+                    buffer: cells,
+                })
+            }
+        }
+    }
+
+    /// Starts copying every cell back from the GPU without waiting for the device, or
+    /// `None` on the CPU, where `cells` has them right away
+    pub fn read_cells(&self) -> Option<PendingCells> {
+        let Backend::Gpu(gpu) = &self.backend else {
+            return None;
+        };
+        let buffer = copy_grid(&gpu.device, &gpu.queue, gpu.grids.buffer());
+        let mapped = crate::readback::map(buffer.slice(..));
+        Some(PendingCells {
+            buffer,
+            size: self.size,
+            mapped,
+        })
+    }
+
+    /// Replaces every cell, and the size of the grid with that of `grid`. Fails and
+    /// keeps the cells if `grid` exceeds the limits of the device.
+    pub fn set_cells(&mut self, grid: &Grid<Particle>) -> io::Result<()> {
+        self.check_limits(grid_size(grid))?;
+        self.write_cells(grid);
+        Ok(())
+    }
+
+    /// Replaces every cell, and the size of the grid with that of `grid`, which is within
+    /// the limits of the device
+    fn write_cells(&mut self, grid: &Grid<Particle>) {
+        let size = grid_size(grid);
+        self.electronics = has_electronics(grid);
+        match &mut self.backend {
+            Backend::Cpu(cells) => {
+                *cells = Grid {
+                    w: grid.w,
+                    h: grid.h,
+                    buffer: grid.buffer.clone(),
+                };
+            }
+            Backend::Gpu(gpu) if size == self.size => {
+                gpu.queue
+                    .write_buffer(gpu.grids.buffer(), 0, bytemuck::cast_slice(&grid.buffer));
+            }
+            Backend::Gpu(gpu) => {
+                gpu.replace_grids(grid);
+            }
+        }
+        self.size = size;
+    }
+
+    /// Fails if a grid of `size` exceeds the limits of the device
+    fn check_limits(&self, size: UVec2) -> io::Result<()> {
+        match &self.backend {
+            Backend::Cpu(_) => Ok(()),
+            Backend::Gpu(gpu) => check_limits(&gpu.device, size),
+        }
+    }
+
+    /// Number of cells of every material, indexed by `ParticleType`, while running the
+    /// falling sand. On the GPU this starts a new count and returns the latest one that
+    /// arrived since the last call, if any, without waiting for the device.
+    pub fn count(&mut self) -> Option<Result<[u32; ParticleType::COUNT], wgpu::BufferAsyncError>> {
+        if !self.automaton.rules().materials() {
+            return None;
+        }
+        match &mut self.backend {
+            Backend::Cpu(grid) => {
+                let mut counts = [0; ParticleType::COUNT];
+                // Cells of no material, which only a bad grid has, are not counted
+                for particle in &grid.buffer {
+                    if let Some(count) = counts.get_mut(particle.behaviour() as usize) {
+                        *count += 1;
+                    }
+                }
+                Some(Ok(counts))
+            }
+            Backend::Gpu(gpu) => gpu.count(self.size),
+        }
+    }
+
+    /// Changes the size of the grid, keeping the overlapping cells. The grids are aligned
+    /// at the bottom left so that settled particles stay on the floor. New cells are
+    /// empty, or the background state of another automaton. Fails and keeps the grid if
+    /// `new_size` exceeds the limits of the device.
+    pub fn resize(&mut self, new_size: UVec2) -> io::Result<()> {
+        let old_size = self.size;
+        if new_size == old_size {
+            return Ok(());
+        }
+        self.check_limits(new_size)?;
+        let size = old_size.min(new_size);
+        let empty = self
+            .automaton
+            .new_grid(new_size.x as usize, new_size.y as usize, false);
+        match &mut self.backend {
+            Backend::Cpu(grid) => {
+                let mut resized = empty;
+                for y in 1..=size.y {
+                    let from = ((old_size.y - y) * old_size.x) as usize;
+                    let to = ((new_size.y - y) * new_size.x) as usize;
+                    resized.buffer[to..to + size.x as usize]
+                        .copy_from_slice(&grid.buffer[from..from + size.x as usize]);
+                }
+                *grid = resized;
+            }
+            Backend::Gpu(gpu) => {
+                let old_grid = gpu.replace_grids(&empty);
+                let mut encoder =
+                    gpu.device
+                        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                            label: Some("Resize Encoder"),
+                        });
+                for y in 1..=size.y {
+                    encoder.copy_buffer_to_buffer(
+                        &old_grid,
+                        ((old_size.y - y) * old_size.x) as u64 * PARTICLE_SIZE,
+                        gpu.grids.buffer(),
+                        ((new_size.y - y) * new_size.x) as u64 * PARTICLE_SIZE,
+                        size.x as u64 * PARTICLE_SIZE,
+                    );
+                }
+                gpu.queue.submit(Some(encoder.finish()));
+            }
+        }
+        self.size = new_size;
+        Ok(())
+    }
+
+    /// Writes the cells to `path`, see `load`. On the GPU this waits for the device.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let grid = self.cells().map_err(io::Error::other)?;
+        let mut bytes = Vec::with_capacity(MAGIC.len() + (3 + grid.buffer.len()) * 4);
+        bytes.extend_from_slice(MAGIC);
+        for word in [self.automaton as u32, grid.w as u32, grid.h as u32]
+            .into_iter()
+            .chain(grid.buffer.iter().map(|particle| particle.to_bits()))
+        {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        std::fs::write(path, bytes)
+    }
+
+    /// Replaces the automaton, the cells and the size of the grid with those saved to
+    /// `path`
+    pub fn load(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let bytes = std::fs::read(path)?;
+        let (automaton, grid) = decode(&bytes).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "Not a saved simulation grid")
+        })?;
+        self.set_cells(&grid)?;
+        self.automaton = automaton;
+        Ok(())
+    }
+}
+
+/// Used by the app, which draws the grid of the simulation on the same device
+#[cfg(feature = "app")]
+impl Simulation {
+    /// Carries the progress of a previous simulation over, such as one on a lost device
+    pub(crate) fn restore_progress(&mut self, steps: u64) {
+        self.steps = steps;
+    }
+
+    fn gpu(&self) -> &Gpu {
+        match &self.backend {
+            Backend::Gpu(gpu) => gpu,
+            Backend::Cpu(_) => panic!("The simulation runs on the CPU"),
+        }
+    }
+
+    pub(crate) fn grid_buffer(&self) -> &wgpu::Buffer {
+        self.gpu().grids.buffer()
+    }
+
+    /// Layout of the grid, which is bound at set 0 by the shaders that draw it
+    pub(crate) fn grid_bind_group_layout(&self) -> &BindGroupLayout {
+        &self.gpu().storage_layout
+    }
+
+    /// Bind group of the buffer currently holding the cells, which changes as the
+    /// simulation steps
+    pub(crate) fn grid_bind_group(&self) -> &wgpu::BindGroup {
+        self.gpu().grids.bind_group()
+    }
+
+    /// Recreates the pipelines from `modules`. The current pipelines are kept if any of
+    /// the new ones fails validation.
+    pub(crate) fn new_module(&mut self, modules: &CompiledShaderModules) -> Result<(), String> {
+        let Backend::Gpu(gpu) = &mut self.backend else {
+            return Ok(());
+        };
+        gpu.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let pipelines = create_pipelines(
+            &gpu.device,
+            &gpu.pipeline_layout,
+            modules,
+            gpu.spirv_passthrough,
+        );
+        let error = futures::executor::block_on(gpu.device.pop_error_scope());
+        let pipelines = pipelines?;
+        match error {
+            Some(err) => Err(err.to_string()),
+            None => {
+                gpu.pipelines = pipelines;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Gpu {
+    fn bind(&self, cpass: &mut wgpu::ComputePass, constants: ComputeConstants) {
+        let bind_groups = [
+            self.grids.bind_group(),
+            &self.counts_bind_group,
+            self.grids.scratch_bind_group(),
+        ];
+        for (i, bind_group) in bind_groups.into_iter().enumerate() {
+            cpass.set_bind_group(i as u32, bind_group, &[]);
+        }
+        self.shader_constants.set_compute(
+            cpass,
+            bind_groups.len() as u32,
+            bytemuck::bytes_of(&constants),
+        );
+    }
+
+    /// Records the dispatches of `schedule` into one compute pass
+    fn record(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        automaton: AutomatonKind,
+        size: UVec2,
+        schedule: &[Dispatch],
+        timestamp_writes: Option<wgpu::ComputePassTimestampWrites>,
+    ) {
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Step Pass"),
+            timestamp_writes,
+        });
+        let step = &self.pipelines.steps[automaton as usize];
+        for &dispatch in schedule {
+            match dispatch {
+                Dispatch::Blocks { offset } => {
+                    cpass.set_pipeline(step);
+                    self.bind(&mut cpass, compute_constants(size, offset));
+                    cpass.dispatch_workgroups(
+                        (size.x / 2).div_ceil(16),
+                        (size.y / 2).div_ceil(16),
+                        1,
+                    );
+                }
+                Dispatch::Cells => {
+                    cpass.set_pipeline(step);
+                    self.bind(&mut cpass, compute_constants(size, 0));
+                    cpass.dispatch_workgroups(size.x.div_ceil(16), size.y.div_ceil(16), 1);
+                    self.grids.swap();
+                }
+                Dispatch::Tiled { offset } => {
+                    cpass.set_pipeline(&self.pipelines.tiled);
+                    self.bind(&mut cpass, compute_constants(size, offset));
+                    cpass.dispatch_workgroups(
+                        size.x.div_ceil(tile::TILE),
+                        size.y.div_ceil(tile::TILE),
+                        1,
+                    );
+                    self.grids.swap();
+                }
+                Dispatch::Electrics => {
+                    cpass.set_pipeline(&self.pipelines.electrics);
+                    self.bind(&mut cpass, compute_constants(size, 0));
+                    cpass.dispatch_workgroups(size.x.div_ceil(16), size.y.div_ceil(16), 1);
+                    self.grids.swap();
+                }
+            }
+        }
+    }
+
+    /// Starts counting the materials, unless the earlier counts are all still on their
+    /// way, and returns the latest counts that arrived
+    fn count(
+        &mut self,
+        size: UVec2,
+    ) -> Option<Result<[u32; ParticleType::COUNT], wgpu::BufferAsyncError>> {
+        let counts = self.counter.poll(&self.device, |(), bytes| {
+            bytemuck::pod_read_unaligned(bytes)
+        });
+        let Some(staging) = self.counter.take_free() else {
+            return counts;
+        };
+        self.shader_constants.reserve(&self.device, 1);
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Count Encoder"),
+            });
+        encoder.clear_buffer(&self.counts, 0, None);
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Count Pass"),
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&self.pipelines.count);
+            self.bind(&mut cpass, compute_constants(size, 0));
+            cpass.dispatch_workgroups(size.x.div_ceil(16), size.y.div_ceil(16), 1);
+        }
+        encoder.copy_buffer_to_buffer(&self.counts, 0, &staging, 0, COUNTS_SIZE);
+        self.shader_constants.upload(&self.queue);
+        self.queue.submit(Some(encoder.finish()));
+        self.counter.map(staging, ());
+        counts
+    }
+
+    /// Recreates the grids with the contents of `grid` and returns the old grid buffer
+    fn replace_grids(&mut self, grid: &Grid<Particle>) -> wgpu::Buffer {
+        let grids = Grids::new(&self.device, &self.storage_layout, grid);
+        let old = std::mem::replace(&mut self.grids, grids);
+        let [first, second] = old.buffers;
+        if old.current == 0 {
+            first
+        } else {
+            second
+        }
+    }
+}
+
+impl Grids {
+    fn new(device: &wgpu::Device, layout: &BindGroupLayout, grid: &Grid<Particle>) -> Self {
+        let buffers = ["Grid", "Scratch Grid"].map(|label| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{label} Buffer")),
+                contents: bytemuck::cast_slice(&grid.buffer),
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+            })
+        });
+        let bind_groups = [0, 1].map(|i| create_bind_group(device, layout, "Grid", &buffers[i]));
+        Self {
+            buffers,
+            bind_groups,
+            current: 0,
+        }
+    }
+
+    fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffers[self.current]
+    }
+
+    fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_groups[self.current]
+    }
+
+    fn scratch_bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_groups[1 - self.current]
+    }
+
+    /// Makes the scratch grid the grid, after a pass wrote every cell to it
+    fn swap(&mut self) {
+        self.current = 1 - self.current;
+    }
+}
+
+/// The compute shaders only read the size and the offset
+fn compute_constants(size: UVec2, offset: u32) -> ComputeConstants {
+    ComputeConstants {
+        size,
+        offset,
+        ..ComputeConstants::zeroed()
+    }
+}
+
+/// Whether the electronics step `particle`, which holds for the electronics materials
+/// and for water
+fn conducts(particle: Particle) -> bool {
+    electrics::state(particle) != Wireworld::Empty
+}
+
+fn has_electronics(grid: &Grid<Particle>) -> bool {
+    grid.buffer.iter().any(|&particle| conducts(particle))
+}
+
+fn grid_size(grid: &Grid<Particle>) -> UVec2 {
+    uvec2(grid.w as u32, grid.h as u32)
+}
+
+fn decode(bytes: &[u8]) -> Option<(AutomatonKind, Grid<Particle>)> {
+    let words = bytes.strip_prefix(MAGIC)?;
+    if words.len() % 4 != 0 {
+        return None;
+    }
+    let mut words = words
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()));
+    let automaton = words.next()?;
+    if automaton as usize >= AutomatonKind::COUNT {
+        return None;
+    }
+    let automaton = AutomatonKind::from_value(automaton);
+    let (w, h) = (words.next()? as usize, words.next()? as usize);
+    let buffer: Vec<_> = words.map(Particle::from_bits).collect();
+    let grid = Grid { w, h, buffer };
+    (w >= 2 && h >= 2 && grid.buffer.len() == w * h && automaton.valid(&grid))
+        .then_some((automaton, grid))
+}
+
+/// Fails if a grid of `size` does not fit into a storage buffer of `device`, needs more
+/// workgroups than a dispatch can have or is larger than a texture, as the canvas is
+fn check_limits(device: &wgpu::Device, size: UVec2) -> io::Result<()> {
+    let limits = device.limits();
+    let bytes = size.x as u64 * size.y as u64 * PARTICLE_SIZE;
+    let max_bytes = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+    let max_side = limits
+        .max_compute_workgroups_per_dimension
+        .saturating_mul(16)
+        .min(limits.max_texture_dimension_2d);
+    let error = if bytes > max_bytes {
+        format!("The grid needs {bytes} bytes, but the device allows at most {max_bytes}")
+    } else if size.max_element() > max_side {
+        format!("The grid can be at most {max_side} cells wide and high on the device")
+    } else {
+        return Ok(());
+    };
+    Err(io::Error::new(io::ErrorKind::InvalidInput, error))
+}
+
+/// Copies the whole grid into a new buffer that can be mapped for reading
+fn copy_grid(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    grid_buffer: &wgpu::Buffer,
+) -> wgpu::Buffer {
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Grid Readback Buffer"),
+        size: grid_buffer.size(),
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Grid Readback Encoder"),
+    });
+    encoder.copy_buffer_to_buffer(grid_buffer, 0, &buffer, 0, grid_buffer.size());
+    queue.submit(Some(encoder.finish()));
+    buffer
+}
+
+fn create_pipelines(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    modules: &CompiledShaderModules,
+    spirv_passthrough: bool,
+) -> Result<Pipelines, String> {
+    let step_module_descr = modules.spv_module_for_entry_point(entry_points::main_cs)?;
+    // Only create the modules of the other entry points if they are separate
+    let separate_module = |entry_point| -> Result<_, String> {
+        let module_descr = modules.spv_module_for_entry_point(entry_point)?;
+        let same_module = std::ptr::eq(&step_module_descr.source[..], &module_descr.source[..]);
+        Ok((!same_module).then(|| shader::create_module(device, spirv_passthrough, module_descr)))
+    };
+    let tiled_module = separate_module(entry_points::main_tiled_cs)?;
+    let count_module = separate_module(entry_points::count_cs)?;
+    let electrics_module = separate_module(entry_points::electrics_cs)?;
+    let step_modules = AutomatonKind::iter()
+        .map(|automaton| separate_module(automaton.rules().step_entry_point()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let step_module = shader::create_module(device, spirv_passthrough, step_module_descr);
+
+    let create = |label: &str, module: Option<&wgpu::ShaderModule>, entry_point| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            module: module.unwrap_or(&step_module),
+            entry_point,
+            compilation_options: Default::default(),
+            cache: None,
+        })
+    };
+    let steps = AutomatonKind::iter()
+        .zip(&step_modules)
+        .map(|(automaton, module)| {
+            let label = format!("{} Step Pipeline", automaton.name());
+            create(
+                &label,
+                module.as_ref(),
+                automaton.rules().step_entry_point(),
+            )
+        })
+        .collect();
+    Ok(Pipelines {
+        steps,
+        tiled: create(
+            "Tiled Pipeline",
+            tiled_module.as_ref(),
+            entry_points::main_tiled_cs,
+        ),
+        count: create(
+            "Count Pipeline",
+            count_module.as_ref(),
+            entry_points::count_cs,
+        ),
+        electrics: create(
+            "Electrics Pipeline",
+            electrics_module.as_ref(),
+            entry_points::electrics_cs,
+        ),
+    })
+}
+
+fn create_storage_bind_group_layout(device: &wgpu::Device, label: &str) -> BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some(&format!("{label} Bind Group Layout")),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    })
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &BindGroupLayout,
+    label: &str,
+    buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(&format!("{label} Bind Group")),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_load_round_trip() {
+        let mut sim = Simulation::on_cpu(Grid::new(37, 21));
+        sim.paint(uvec2(18, 10), 6, ParticleType::Sand);
+        sim.paint(uvec2(5, 5), 3, ParticleType::Water);
+        sim.step(10);
+        let path = std::env::temp_dir().join(format!("runner-test-{}.grid", std::process::id()));
+        sim.save(&path).unwrap();
+
+        let mut loaded = Simulation::on_cpu(Grid::new(2, 2));
+        let result = loaded.load(&path);
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+        assert_eq!(loaded.size(), sim.size());
+        assert_eq!(loaded.automaton(), AutomatonKind::Sand);
+        let (saved, loaded) = (sim.cells().unwrap(), loaded.cells().unwrap());
+        assert_eq!(cpu::mismatches(&saved, &loaded), 0);
+    }
+
+    #[test]
+    fn tiled_matches_untiled_with_electronics() {
+        let grid = || {
+            Grid::from_fn(48, 40, |x, y| match (x % 12, y % 10) {
+                (0, _) | (_, 0) => Particle::new_from_tone(ParticleType::Conductor, 0.0),
+                (1, 1) => Particle::new_from_tone(ParticleType::ElectronHead, 0.0),
+                (5, _) => Particle::new_from_tone(ParticleType::Water, 0.0),
+                (7, _) => Particle::new_from_tone(ParticleType::Sand, 0.0),
+                _ => Particle::empty_from_tone(0.0),
+            })
+        };
+        let mut tiled = Simulation::on_cpu(grid());
+        tiled.set_tiled(true);
+        let mut untiled = Simulation::on_cpu(grid());
+        // Runs that start and end between multiples of `tile::STEPS`
+        for sub_steps in [3, tile::STEPS * 2 + 5, tile::STEPS - 1, tile::STEPS] {
+            tiled.step(sub_steps);
+            untiled.step(sub_steps);
+            let (tiled, untiled) = (tiled.cells().unwrap(), untiled.cells().unwrap());
+            assert_eq!(cpu::mismatches(&tiled, &untiled), 0);
+        }
+    }
+
+    #[test]
+    fn switching_automata_clears_the_cells() {
+        let mut sim = Simulation::on_cpu(Grid::new(16, 16));
+        sim.set_automaton(AutomatonKind::LangtonsAnt);
+        sim.paint_state(uvec2(8, 8), 2, 2).unwrap();
+        sim.step(20);
+        let path = std::env::temp_dir().join(format!("runner-ant-{}.grid", std::process::id()));
+        sim.save(&path).unwrap();
+
+        sim.set_automaton(AutomatonKind::Sand);
+        assert_eq!(
+            sim.count().unwrap().unwrap()[ParticleType::Empty as usize],
+            16 * 16
+        );
+        assert!(sim.paint_state(uvec2(8, 8), 2, u32::MAX).is_err());
+        let result = sim.load(&path);
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+        assert_eq!(sim.automaton(), AutomatonKind::LangtonsAnt);
+    }
+
+    #[test]
+    fn decode_rejects_bad_input() {
+        let encode = |automaton: u32, w: u32, h: u32, cell: u32, cells: usize| {
+            let mut bytes = MAGIC.to_vec();
+            for word in [automaton, w, h].into_iter().chain(vec![cell; cells]) {
+                bytes.extend_from_slice(&word.to_le_bytes());
+            }
+            bytes
+        };
+        let ant = AutomatonKind::LangtonsAnt as u32;
+        assert!(decode(&encode(0, 3, 2, 0, 6)).is_some());
+        assert_eq!(
+            decode(&encode(ant, 3, 2, 2, 6)).map(|(automaton, _)| automaton),
+            Some(AutomatonKind::LangtonsAnt)
+        );
+        assert!(decode(b"").is_none());
+        assert!(decode(b"NOTAGRID\0\0\0\0\x03\0\0\0\x02\0\0\0").is_none());
+        // Unknown automata, and cells the automaton cannot have
+        assert!(decode(&encode(AutomatonKind::COUNT as u32, 3, 2, 0, 6)).is_none());
+        assert!(decode(&encode(0, 3, 2, 0xff, 6)).is_none());
+        // Missing, surplus and partial cells
+        assert!(decode(&encode(0, 3, 2, 0, 5)).is_none());
+        assert!(decode(&encode(0, 3, 2, 0, 7)).is_none());
+        assert!(decode(&encode(0, 3, 2, 0, 6)[..MAGIC.len() + 12 + 23]).is_none());
+        // Too small to step a Margolus block
+        assert!(decode(&encode(0, 1, 4, 0, 4)).is_none());
+        assert!(decode(&encode(0, 0, 0, 0, 0)).is_none());
+    }
+}
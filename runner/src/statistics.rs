@@ -0,0 +1,102 @@
+use egui::{Color32, Sense, Shape, Stroke};
+use shared::particle::ParticleType;
+use std::collections::VecDeque;
+
+const HISTORY_LEN: usize = 600;
+
+pub type Counts = [u32; ParticleType::COUNT];
+
+pub struct Statistics {
+    pub counts: Counts,
+    history: VecDeque<Counts>,
+    open: bool,
+}
+
+impl Statistics {
+    pub fn new() -> Self {
+        Self {
+            counts: [0; ParticleType::COUNT],
+            history: VecDeque::with_capacity(HISTORY_LEN),
+            open: false,
+        }
+    }
+
+    /// Counting is only done while the panel is open
+    pub fn enabled(&self) -> bool {
+        self.open
+    }
+
+    pub fn push(&mut self, counts: Counts) {
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(counts);
+        self.counts = counts;
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let resp = egui::CollapsingHeader::new("Statistics").show(ui, |ui| {
+            let prev = self
+                .history
+                .iter()
+                .nth_back(1)
+                .copied()
+                .unwrap_or(self.counts);
+            egui::Grid::new("statistics").show(ui, |ui| {
+                for particle_type in ParticleType::iter() {
+                    let i = particle_type as usize;
+                    let delta = self.counts[i] as i64 - prev[i] as i64;
+                    ui.colored_label(color(particle_type), particle_type.name());
+                    ui.label(self.counts[i].to_string());
+                    ui.label(format!("{delta:+}"));
+                    ui.end_row();
+                }
+            });
+            self.graph(ui);
+        });
+        self.open = resp.body_returned.is_some();
+        if !self.open {
+            self.history.clear();
+        }
+    }
+
+    fn graph(&self, ui: &mut egui::Ui) {
+        let size = egui::vec2(ui.available_width(), 80.0);
+        let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_stroke(rect, 0.0, ui.visuals().widgets.noninteractive.bg_stroke);
+
+        // Empty cells dwarf everything else, so only materials are plotted
+        let materials = ParticleType::iter().filter(|&t| t != ParticleType::Empty);
+        let max = self
+            .history
+            .iter()
+            .flat_map(|counts| counts[1..].iter())
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(1) as f32;
+        let dx = rect.width() / (HISTORY_LEN - 1) as f32;
+        for particle_type in materials {
+            let points = self
+                .history
+                .iter()
+                .enumerate()
+                .map(|(i, counts)| {
+                    let y = counts[particle_type as usize] as f32 / max;
+                    egui::pos2(
+                        rect.left() + i as f32 * dx,
+                        rect.bottom() - y * rect.height(),
+                    )
+                })
+                .collect();
+            painter.add(Shape::line(points, Stroke::new(1.0, color(particle_type))));
+        }
+    }
+}
+
+fn color(particle_type: ParticleType) -> Color32 {
+    let (c1, c2) = particle_type.color_range();
+    let [r, g, b] = (c1.lerp(c2, 0.5) * 255.0).to_array().map(|x| x as u8);
+    Color32::from_rgb(r, g, b)
+}
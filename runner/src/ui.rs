@@ -1,4 +1,10 @@
-use crate::{controller::Controller, fps_counter::FpsCounter, user_event::UserEvent};
+use crate::{
+    context::DeviceInfo,
+    controller::Controller,
+    fps_counter::{FpsCounter, GpuTimes},
+    settings::{Settings, UiSettings},
+    user_event::UserEvent,
+};
 use egui::{
     epaint::{textures::TexturesDelta, ClippedPrimitive},
     Context,
@@ -7,20 +13,79 @@ use egui_winit::{
     winit::{event::WindowEvent, event_loop::EventLoopProxy, window::Window},
     State,
 };
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
+use web_time::Instant;
+
+/// How long a successful shader reload is shown for
+const RELOADED_TOAST_DURATION: Duration = Duration::from_secs(3);
+
+pub struct UiOutput {
+    pub clipped_primitives: Vec<ClippedPrimitive>,
+    pub textures_delta: TexturesDelta,
+    pub pixels_per_point: f32,
+}
 
 pub struct UiState {
     pub fps: usize,
     pub show_fps: bool,
+    pub show_gpu_times: bool,
+    /// Whether the device supports timestamp queries
+    pub gpu_times_supported: bool,
+    pub gpu_times: GpuTimes,
+    /// Set once the device exists
+    pub device_info: Option<DeviceInfo>,
     pub vsync: bool,
+    pub show_sidebar: bool,
+    /// Outcome of the latest shader reload and when it happened, until dismissed
+    pub shader_reload: Option<(ShaderReload, Instant)>,
+    /// When the shaders were last reloaded successfully
+    pub last_reload: Option<Instant>,
+}
+
+pub enum ShaderReload {
+    Reloaded,
+    Failed(String),
 }
 
 impl UiState {
-    pub fn new() -> Self {
+    pub fn new(settings: &UiSettings) -> Self {
         Self {
             fps: 0,
-            show_fps: true,
-            vsync: true,
+            show_fps: settings.show_fps,
+            show_gpu_times: settings.show_gpu_times,
+            gpu_times_supported: false,
+            gpu_times: GpuTimes::new(),
+            device_info: None,
+            vsync: settings.vsync,
+            show_sidebar: settings.show_sidebar,
+            shader_reload: None,
+            last_reload: None,
+        }
+    }
+
+    pub fn apply_settings(&mut self, settings: &UiSettings) {
+        self.show_fps = settings.show_fps;
+        self.show_gpu_times = settings.show_gpu_times;
+        self.vsync = settings.vsync;
+        self.show_sidebar = settings.show_sidebar;
+    }
+
+    pub fn shaders_reloaded(&mut self) {
+        let now = Instant::now();
+        self.shader_reload = Some((ShaderReload::Reloaded, now));
+        self.last_reload = Some(now);
+    }
+
+    pub fn shader_reload_failed(&mut self, error: String) {
+        self.shader_reload = Some((ShaderReload::Failed(error), Instant::now()));
+    }
+
+    pub fn settings(&self) -> UiSettings {
+        UiSettings {
+            show_fps: self.show_fps,
+            show_gpu_times: self.show_gpu_times,
+            vsync: self.vsync,
+            show_sidebar: self.show_sidebar,
         }
     }
 }
@@ -62,7 +127,7 @@ impl Ui {
         window: &Window,
         ui_state: &mut UiState,
         controller: &mut Controller,
-    ) -> (Vec<ClippedPrimitive>, TexturesDelta) {
+    ) -> UiOutput {
         ui_state.fps = self.fps_counter.tick();
         let raw_input = self.egui_winit_state.take_egui_input(window);
         let full_output = self.egui_winit_state.egui_ctx().run(raw_input, |ctx| {
@@ -74,7 +139,11 @@ impl Ui {
             .egui_winit_state
             .egui_ctx()
             .tessellate(full_output.shapes, full_output.pixels_per_point);
-        (clipped_primitives, full_output.textures_delta)
+        UiOutput {
+            clipped_primitives,
+            textures_delta: full_output.textures_delta,
+            pixels_per_point: full_output.pixels_per_point,
+        }
     }
 
     fn send_event(&self, event: UserEvent) {
@@ -82,28 +151,104 @@ impl Ui {
     }
 
     fn ui(&self, ctx: &Context, ui_state: &mut UiState, controller: &mut Controller) {
-        let resp = egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
+        egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("Settings", |ui| {
                     ui.checkbox(&mut ui_state.show_fps, "fps counter");
+                    ui.add_enabled(
+                        ui_state.gpu_times_supported,
+                        egui::Checkbox::new(&mut ui_state.show_gpu_times, "GPU times"),
+                    )
+                    .on_disabled_hover_text("The adapter does not support timestamp queries");
                     if ui.checkbox(&mut ui_state.vsync, "V-Sync").clicked() {
                         self.send_event(UserEvent::SetVSync(ui_state.vsync));
                     }
+                    if let Some(device_info) = &ui_state.device_info {
+                        ui.menu_button("Adapter", |ui| device_info.ui(ui));
+                    }
+                    ui.separator();
+                    if ui.button("Reset to defaults").clicked() {
+                        let settings = Settings::default();
+                        ui_state.apply_settings(&settings.ui);
+                        controller.apply_settings(&settings.controller);
+                        self.send_event(UserEvent::SetVSync(ui_state.vsync));
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("View", |ui| {
+                    ui.checkbox(&mut ui_state.show_sidebar, "Sidebar");
                 });
-                if ui_state.show_fps {
+                ui.menu_button("Simulation", |ui| controller.simulation_menu(ui));
+                let show_gpu_times = ui_state.show_gpu_times && ui_state.gpu_times_supported;
+                if ui_state.show_fps || show_gpu_times {
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         ui.style_mut().interaction.selectable_labels = false;
-                        ui.label(format!("FPS: {}", ui_state.fps));
+                        if ui_state.show_fps {
+                            ui.label(format!("FPS: {}", ui_state.fps));
+                        }
+                        if show_gpu_times {
+                            ui_state.gpu_times.ui(ui);
+                        }
                     });
                 }
             });
         });
-        debug_assert_eq!(resp.response.rect.height(), shared::UI_MENU_HEIGHT as f32);
-        let resp = egui::SidePanel::right("right_panel")
-            .resizable(false)
-            .show(ctx, |ui| {
-                controller.ui(ctx, ui, &self.event_proxy);
+        egui::SidePanel::right("right_panel")
+            .resizable(true)
+            .show_animated(ctx, ui_state.show_sidebar, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    controller.ui(ctx, ui, &self.event_proxy);
+                });
+            });
+        shader_reload_toast(ctx, ui_state);
+        // egui works in logical points, the simulation in physical pixels
+        let viewport = ctx.available_rect() * ctx.pixels_per_point();
+        let min = glam::vec2(viewport.min.x, viewport.min.y).round();
+        let max = glam::vec2(viewport.max.x, viewport.max.y).round();
+        controller.set_viewport(min, max - min);
+    }
+}
+
+/// Shows the outcome of the latest shader reload in the bottom left corner. Failures stay
+/// until dismissed or fixed, successes disappear on their own.
+fn shader_reload_toast(ctx: &Context, ui_state: &mut UiState) {
+    let Some((reload, at)) = &ui_state.shader_reload else {
+        return;
+    };
+    if matches!(reload, ShaderReload::Reloaded) && at.elapsed() > RELOADED_TOAST_DURATION {
+        ui_state.shader_reload = None;
+        return;
+    }
+    let mut dismissed = false;
+    egui::Window::new("Shader Reload")
+        .anchor(egui::Align2::LEFT_BOTTOM, [8.0, -8.0])
+        .title_bar(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            match reload {
+                ShaderReload::Reloaded => {
+                    ui.colored_label(egui::Color32::LIGHT_GREEN, "Shaders reloaded");
+                }
+                ShaderReload::Failed(error) => {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(ui.visuals().error_fg_color, "Shader reload failed");
+                        dismissed = ui.small_button("Dismiss").clicked();
+                    });
+                    // The compiler output can be long
+                    egui::ScrollArea::both().max_height(300.0).show(ui, |ui| {
+                        ui.add(egui::Label::new(egui::RichText::new(error).monospace()).extend());
+                    });
+                    ui.label("The previous shaders are still in use");
+                }
+            }
+            ui.weak(match ui_state.last_reload {
+                Some(last_reload) => {
+                    format!("Last reload {} s ago", last_reload.elapsed().as_secs())
+                }
+                None => "Not reloaded since startup".to_string(),
             });
-        debug_assert_eq!(resp.response.rect.width(), shared::UI_SIDEBAR_WIDTH as f32);
+        });
+    if dismissed {
+        ui_state.shader_reload = None;
     }
 }
@@ -1,8 +1,16 @@
 use crate::app::Graphics;
+use crate::context::ContextError;
 use crate::shader::CompiledShaderModules;
 
 pub enum UserEvent {
+    /// Sent by the shader watcher, which only exists with hot reloading
+    #[cfg_attr(not(hot_reload), allow(dead_code))]
     NewModule(CompiledShaderModules),
+    /// A rebuild of the watched shaders failed, the old modules stay in use
+    #[cfg_attr(not(hot_reload), allow(dead_code))]
+    ShaderBuildFailed(String),
     SetVSync(bool),
     CreateWindow(Graphics),
+    /// No device could be created for the window
+    InitFailed(ContextError),
 }
@@ -1,14 +1,16 @@
 #![no_std]
 
+use automata::Automaton;
 use push_constants::sandsim::*;
 use shared::gridref::*;
 use shared::particle::*;
 use shared::*;
+use spirv_std::arch::{atomic_i_add, workgroup_memory_barrier_with_group_sync};
 use spirv_std::glam::*;
+use spirv_std::image::Image2d;
+use spirv_std::memory::{Scope, Semantics};
 use spirv_std::num_traits::Float;
-use spirv_std::spirv;
-
-mod update;
+use spirv_std::{spirv, Image, Sampler};
 
 fn distance_sq_to_line_segment(p: Vec2, v: Vec2, w: Vec2) -> f32 {
     // Return the distance squared between point p and line segment vw
@@ -25,66 +27,258 @@ fn distance_sq_to_line_segment(p: Vec2, v: Vec2, w: Vec2) -> f32 {
     return p.distance_squared(projection);
 }
 
+/// Whether the brush stroke since the last frame covers the cell centred at `pos`
+fn brush_covers(constants: &FragmentConstants, pos: Vec2) -> bool {
+    let cursor_down = constants.cursor_down != 0;
+    if !cursor_down {
+        return false;
+    }
+    let prev_cursor = zoom(constants.prev_cursor, constants);
+    let cursor = zoom(constants.cursor, constants);
+    // `pos` is the centre of the cell. Reach as far as the outermost pixel centre
+    // inside the cell, so that zoomed in cells paint as if tested per pixel. Cells
+    // smaller than a pixel are tested at their centre.
+    let reach = constants.brush_size_sq.sqrt() + 0.5 - 0.5 / constants.zoom.max(1.0);
+    distance_sq_to_line_segment(pos, prev_cursor, cursor) < reach * reach
+}
+
 fn handle_cursor_down(constants: &FragmentConstants, pos: Vec2, grid: &mut GridRefMut<Particle>) {
-    if constants.cursor_down.into() {
-        let height = constants.size.height;
-        let prev_cursor = zoom(constants.prev_cursor.into(), constants);
-        let cursor = zoom(constants.cursor.into(), constants);
-        if distance_sq_to_line_segment(pos, prev_cursor, cursor) < constants.brush_size_sq {
-            let tone = rand(pos * (constants.time + 1.0));
-            let particle_type = ParticleType::from_value(constants.current_particle_type);
-            let particle = Particle::new_from_tone(particle_type, tone);
-            grid.set(pos.x as usize, pos.y as usize, particle);
-        }
+    if brush_covers(constants, pos) {
+        let tone = rand(pos * (constants.time + 1.0));
+        let particle_type = ParticleType::from_value(constants.current_particle_type);
+        let particle = Particle::new_from_tone(particle_type, tone);
+        grid.set(pos.x as usize, pos.y as usize, particle);
     }
 }
 
 fn zoom(p: Vec2, constants: &FragmentConstants) -> Vec2 {
     let zoom = constants.zoom;
-    let height = constants.size.height as f32;
-    p / zoom + Vec2::Y * (height - height / zoom)
+    let height = constants.size.y as f32;
+    let viewport_height = constants.viewport_size.y;
+    p / zoom + Vec2::Y * (height - viewport_height / zoom)
 }
 
 fn debug(constants: &FragmentConstants, pos: Vec2, output: &mut Vec4) {
     let offset = constants.offset;
-    if constants.zoom > 20.0
-        && ((pos.x as u32 % 2 == offset && pos.x.fract() < 0.025)
-            || (pos.x as u32 % 2 == (1 - offset) && pos.x.fract() > 0.975)
-            || (pos.y as u32 % 2 == offset && pos.y.fract() < 0.025)
-            || (pos.y as u32 % 2 == (1 - offset) && pos.y.fract() > 0.975))
+    // Block parity mode draws the block edges at lower zoom levels, one pixel wide
+    let (min_zoom, width) = match RenderMode::from_value(constants.render_mode) {
+        RenderMode::BlockParity => (4.0, 1.0 / constants.zoom),
+        _ => (20.0, 0.025),
+    };
+    if constants.zoom > min_zoom
+        && ((pos.x as u32 % 2 == offset && pos.x.fract() < width)
+            || (pos.x as u32 % 2 == (1 - offset) && pos.x.fract() > 1.0 - width)
+            || (pos.y as u32 % 2 == offset && pos.y.fract() < width)
+            || (pos.y as u32 % 2 == (1 - offset) && pos.y.fract() > 1.0 - width))
     {
         *output = Vec3::X.extend(1.0);
     }
 }
 
+fn grey(color: Vec3) -> Vec3 {
+    Vec3::splat(color.dot(vec3(0.299, 0.587, 0.114)))
+}
+
+fn render_mode_color(constants: &FragmentConstants, cell: UVec2, particle: Particle) -> Vec3 {
+    match RenderMode::from_value(constants.render_mode) {
+        RenderMode::Normal => particle.color(),
+        RenderMode::Flat => particle.flat_color(),
+        RenderMode::Activity => {
+            if particle.moved() {
+                vec3(1.0, 0.3, 0.1)
+            } else {
+                grey(particle.color()) * 0.5
+            }
+        }
+        RenderMode::BlockParity => {
+            // Shifted by a whole block so that the first partial block stays unsigned
+            let block = (cell + 2 - constants.offset) / 2;
+            if (block.x + block.y) % 2 == 0 {
+                particle.color()
+            } else {
+                particle.color() * 0.75
+            }
+        }
+        RenderMode::Isolate => {
+            if particle.behaviour() == constants.isolated_material {
+                particle.color()
+            } else {
+                grey(particle.color()) * 0.3
+            }
+        }
+    }
+}
+
 #[spirv(fragment)]
 pub fn main_fs(
     #[spirv(frag_coord)] frag_coord: Vec4,
-    #[spirv(push_constant)] constants: &FragmentConstants,
-    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] grid_buffer: &mut [Particle],
+    #[cfg(not(feature = "constant-buffer"))]
+    #[spirv(push_constant)]
+    constants: &FragmentConstants,
+    #[cfg(feature = "constant-buffer")]
+    #[spirv(uniform, descriptor_set = 1, binding = 0)]
+    constants: &FragmentConstants,
+    #[spirv(descriptor_set = 0, binding = 0)] canvas: &Image2d,
+    #[spirv(descriptor_set = 0, binding = 1)] sampler: &Sampler,
     output: &mut Vec4,
 ) {
-    let coord = vec2(frag_coord.x, frag_coord.y - shared::UI_MENU_HEIGHT as f32);
-    let mut grid = GridRefMut::new(
-        constants.size.width as usize,
-        constants.size.height as usize,
-        grid_buffer,
-    );
-
+    let viewport_offset = constants.viewport_offset;
+    let coord = frag_coord.xy() - viewport_offset;
     let pos = zoom(coord, constants);
-    handle_cursor_down(constants, pos, &mut grid);
+    // Sampled before branching, mip selection needs derivatives in uniform control flow
+    let color = canvas.sample(*sampler, pos / constants.size.as_vec2());
 
-    *output = grid
-        .get(pos.x as usize, pos.y as usize)
-        .color()
-        .powf(2.2)
-        .extend(1.0);
+    if pos.cmplt(Vec2::ZERO).any() || pos.cmpge(constants.size.as_vec2()).any() {
+        *output = vec4(0.1, 0.1, 0.1, 1.0);
+        return;
+    }
+    *output = color;
 
-    if constants.debug.into() {
+    if constants.debug != 0 {
         debug(constants, pos, output);
     }
 }
 
+/// Paints the brush stroke since the last frame into the grid
+#[spirv(compute(threads(16, 16)))]
+pub fn paint_cs(
+    #[spirv(global_invocation_id)] gid: UVec3,
+    #[cfg(not(feature = "constant-buffer"))]
+    #[spirv(push_constant)]
+    constants: &FragmentConstants,
+    #[cfg(feature = "constant-buffer")]
+    #[spirv(uniform, descriptor_set = 2, binding = 0)]
+    constants: &FragmentConstants,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] grid_buffer: &mut [Particle],
+) {
+    let size = constants.size;
+    if gid.x >= size.x || gid.y >= size.y {
+        return;
+    }
+    let mut grid = GridRefMut::new(size.x as usize, size.y as usize, grid_buffer);
+    handle_cursor_down(constants, gid.xy().as_vec2() + 0.5, &mut grid);
+}
+
+/// Writes the display colour of every cell into the canvas texture
+#[spirv(compute(threads(16, 16)))]
+pub fn color_cs(
+    #[spirv(global_invocation_id)] gid: UVec3,
+    #[cfg(not(feature = "constant-buffer"))]
+    #[spirv(push_constant)]
+    constants: &FragmentConstants,
+    #[cfg(feature = "constant-buffer")]
+    #[spirv(uniform, descriptor_set = 2, binding = 0)]
+    constants: &FragmentConstants,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] grid_buffer: &[Particle],
+    #[spirv(descriptor_set = 1, binding = 0)] canvas: &Image!(
+        2D,
+        format = rgba16f,
+        sampled = false
+    ),
+) {
+    let size = constants.size;
+    if gid.x >= size.x || gid.y >= size.y {
+        return;
+    }
+    let grid = GridRef::new(size.x as usize, size.y as usize, grid_buffer);
+    let cell = gid.xy();
+    let particle = grid.get(cell.x as usize, cell.y as usize);
+    let color = render_mode_color(constants, cell, particle).powf(2.2);
+    unsafe {
+        canvas.write(cell, (color * (1.0 + particle.emissive())).extend(1.0));
+    }
+}
+
+/// Paints the brush stroke since the last frame into the grid of one of the `automata`,
+/// writing `current_particle_type` as the bits of the painted state
+#[spirv(compute(threads(16, 16)))]
+pub fn state_paint_cs(
+    #[spirv(global_invocation_id)] gid: UVec3,
+    #[cfg(not(feature = "constant-buffer"))]
+    #[spirv(push_constant)]
+    constants: &FragmentConstants,
+    #[cfg(feature = "constant-buffer")]
+    #[spirv(uniform, descriptor_set = 2, binding = 0)]
+    constants: &FragmentConstants,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] grid_buffer: &mut [u32],
+) {
+    let size = constants.size;
+    if gid.x >= size.x || gid.y >= size.y {
+        return;
+    }
+    if brush_covers(constants, gid.xy().as_vec2() + 0.5) {
+        grid_buffer[(gid.y * size.x + gid.x) as usize] = constants.current_particle_type;
+    }
+}
+
+/// Step and colour entry points of an automaton from `automata`. The step writes the
+/// next state of every cell into the scratch grid, which the runner then swaps in.
+macro_rules! automaton_entry_points {
+    ($automaton:ty, $step:ident, $color:ident) => {
+        #[spirv(compute(threads(16, 16)))]
+        pub fn $step(
+            #[spirv(global_invocation_id)] gid: UVec3,
+            #[cfg(not(feature = "constant-buffer"))]
+            #[spirv(push_constant)]
+            constants: &ComputeConstants,
+            #[cfg(feature = "constant-buffer")]
+            #[spirv(uniform, descriptor_set = 3, binding = 0)]
+            constants: &ComputeConstants,
+            #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] grid_buffer: &[u32],
+            #[spirv(storage_buffer, descriptor_set = 2, binding = 0)] out_buffer: &mut [u32],
+        ) {
+            let size = constants.size;
+            if gid.x >= size.x || gid.y >= size.y {
+                return;
+            }
+            let grid = GridRef::new(size.x as usize, size.y as usize, grid_buffer);
+            let cell = automata::step_cell::<$automaton>(&grid, size, gid.xy());
+            out_buffer[(gid.y * size.x + gid.x) as usize] = cell.to_bits();
+        }
+
+        #[spirv(compute(threads(16, 16)))]
+        pub fn $color(
+            #[spirv(global_invocation_id)] gid: UVec3,
+            #[cfg(not(feature = "constant-buffer"))]
+            #[spirv(push_constant)]
+            constants: &FragmentConstants,
+            #[cfg(feature = "constant-buffer")]
+            #[spirv(uniform, descriptor_set = 2, binding = 0)]
+            constants: &FragmentConstants,
+            #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] grid_buffer: &[u32],
+            #[spirv(descriptor_set = 1, binding = 0)] canvas: &Image!(
+                2D,
+                format = rgba16f,
+                sampled = false
+            ),
+        ) {
+            let size = constants.size;
+            if gid.x >= size.x || gid.y >= size.y {
+                return;
+            }
+            let grid = GridRef::new(size.x as usize, size.y as usize, grid_buffer);
+            let cell = <$automaton>::from_bits(grid.get(gid.x as usize, gid.y as usize));
+            let color = cell.color().powf(2.2);
+            unsafe {
+                canvas.write(gid.xy(), (color * (1.0 + cell.emissive())).extend(1.0));
+            }
+        }
+    };
+}
+
+automaton_entry_points!(automata::Life, life_cs, life_color_cs);
+automaton_entry_points!(automata::Wireworld, wireworld_cs, wireworld_color_cs);
+automaton_entry_points!(
+    automata::BriansBrain,
+    brians_brain_cs,
+    brians_brain_color_cs
+);
+automaton_entry_points!(
+    automata::LangtonsAnt,
+    langtons_ant_cs,
+    langtons_ant_color_cs
+);
+
 #[spirv(vertex)]
 pub fn main_vs(
     #[spirv(vertex_index)] vert_id: i32,
@@ -95,17 +289,254 @@ pub fn main_vs(
     *out_pos = pos.extend(0.0).extend(1.0);
 }
 
+/// Average of four bilinear taps, covering a 4x4 texel area of the source
+fn downsample(
+    constants: &BloomConstants,
+    frag_coord: Vec4,
+    image: &Image2d,
+    sampler: Sampler,
+) -> Vec3 {
+    let pixel_size = constants.pixel_size;
+    let uv = frag_coord.xy() * pixel_size;
+    let d = constants.texel_size;
+    let sample = |offset: Vec2| -> Vec3 { image.sample(sampler, uv + offset * d).xyz() };
+    (sample(vec2(-1.0, -1.0))
+        + sample(vec2(1.0, -1.0))
+        + sample(vec2(-1.0, 1.0))
+        + sample(vec2(1.0, 1.0)))
+        * 0.25
+}
+
+/// First step of the bloom chain, keeping only the light above 1.0
+#[spirv(fragment)]
+pub fn bloom_prefilter_fs(
+    #[spirv(frag_coord)] frag_coord: Vec4,
+    #[cfg(not(feature = "constant-buffer"))]
+    #[spirv(push_constant)]
+    constants: &BloomConstants,
+    #[cfg(feature = "constant-buffer")]
+    #[spirv(uniform, descriptor_set = 1, binding = 0)]
+    constants: &BloomConstants,
+    #[spirv(descriptor_set = 0, binding = 0)] image: &Image2d,
+    #[spirv(descriptor_set = 0, binding = 1)] sampler: &Sampler,
+    output: &mut Vec4,
+) {
+    let color = downsample(constants, frag_coord, image, *sampler);
+    *output = (color - Vec3::ONE).max(Vec3::ZERO).extend(1.0);
+}
+
+#[spirv(fragment)]
+pub fn bloom_downsample_fs(
+    #[spirv(frag_coord)] frag_coord: Vec4,
+    #[cfg(not(feature = "constant-buffer"))]
+    #[spirv(push_constant)]
+    constants: &BloomConstants,
+    #[cfg(feature = "constant-buffer")]
+    #[spirv(uniform, descriptor_set = 1, binding = 0)]
+    constants: &BloomConstants,
+    #[spirv(descriptor_set = 0, binding = 0)] image: &Image2d,
+    #[spirv(descriptor_set = 0, binding = 1)] sampler: &Sampler,
+    output: &mut Vec4,
+) {
+    *output = downsample(constants, frag_coord, image, *sampler).extend(1.0);
+}
+
+/// 3x3 tent filter, blended additively onto the next larger level
+#[spirv(fragment)]
+pub fn bloom_upsample_fs(
+    #[spirv(frag_coord)] frag_coord: Vec4,
+    #[cfg(not(feature = "constant-buffer"))]
+    #[spirv(push_constant)]
+    constants: &BloomConstants,
+    #[cfg(feature = "constant-buffer")]
+    #[spirv(uniform, descriptor_set = 1, binding = 0)]
+    constants: &BloomConstants,
+    #[spirv(descriptor_set = 0, binding = 0)] image: &Image2d,
+    #[spirv(descriptor_set = 0, binding = 1)] sampler: &Sampler,
+    output: &mut Vec4,
+) {
+    let pixel_size = constants.pixel_size;
+    let uv = frag_coord.xy() * pixel_size;
+    let d = constants.texel_size;
+    let mut color = Vec3::ZERO;
+    let mut y: i32 = -1;
+    while y <= 1 {
+        let mut x: i32 = -1;
+        while x <= 1 {
+            let weight = ((2 - x.abs()) * (2 - y.abs())) as f32 / 16.0;
+            let offset = vec2(x as f32, y as f32) * d;
+            color += image.sample(*sampler, uv + offset).xyz() * weight;
+            x += 1;
+        }
+        y += 1;
+    }
+    *output = color.extend(1.0);
+}
+
+#[spirv(fragment)]
+pub fn composite_fs(
+    #[spirv(frag_coord)] frag_coord: Vec4,
+    #[cfg(not(feature = "constant-buffer"))]
+    #[spirv(push_constant)]
+    constants: &BloomConstants,
+    #[cfg(feature = "constant-buffer")]
+    #[spirv(uniform, descriptor_set = 2, binding = 0)]
+    constants: &BloomConstants,
+    #[spirv(descriptor_set = 0, binding = 0)] image: &Image2d,
+    #[spirv(descriptor_set = 0, binding = 1)] sampler: &Sampler,
+    #[spirv(descriptor_set = 1, binding = 0)] bloom: &Image2d,
+    #[spirv(descriptor_set = 1, binding = 1)] bloom_sampler: &Sampler,
+    output: &mut Vec4,
+) {
+    let pixel_size = constants.pixel_size;
+    let uv = frag_coord.xy() * pixel_size;
+    let color = image.sample(*sampler, uv).xyz();
+    let glow = bloom.sample(*bloom_sampler, uv).xyz();
+    *output = (color + glow * constants.strength).extend(1.0);
+}
+
 #[spirv(compute(threads(16, 16)))]
 pub fn main_cs(
     #[spirv(global_invocation_id)] gid: UVec3,
-    #[spirv(push_constant)] constants: &ComputeConstants,
+    #[cfg(not(feature = "constant-buffer"))]
+    #[spirv(push_constant)]
+    constants: &ComputeConstants,
+    #[cfg(feature = "constant-buffer")]
+    #[spirv(uniform, descriptor_set = 3, binding = 0)]
+    constants: &ComputeConstants,
     #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] grid_buffer: &mut [Particle],
 ) {
     let mut grid = GridRefMut::new(
-        constants.size.width as usize,
-        constants.size.height as usize,
+        constants.size.x as usize,
+        constants.size.y as usize,
         grid_buffer,
     );
     let pos = gid.xy() * 2 + constants.offset;
-    update::update(pos, &mut grid);
+    if pos.x + 1 >= constants.size.x || pos.y + 1 >= constants.size.y {
+        return;
+    }
+    update::step_block(pos, &mut grid);
+}
+
+/// Steps the electronics materials once, writing every cell to the scratch grid
+#[spirv(compute(threads(16, 16)))]
+pub fn electrics_cs(
+    #[spirv(global_invocation_id)] gid: UVec3,
+    #[cfg(not(feature = "constant-buffer"))]
+    #[spirv(push_constant)]
+    constants: &ComputeConstants,
+    #[cfg(feature = "constant-buffer")]
+    #[spirv(uniform, descriptor_set = 3, binding = 0)]
+    constants: &ComputeConstants,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] grid_buffer: &[Particle],
+    #[spirv(storage_buffer, descriptor_set = 2, binding = 0)] out_buffer: &mut [Particle],
+) {
+    let size = constants.size;
+    if gid.x >= size.x || gid.y >= size.y {
+        return;
+    }
+    let grid = GridRef::new(size.x as usize, size.y as usize, grid_buffer);
+    out_buffer[(gid.y * size.x + gid.x) as usize] = electrics::step_cell(&grid, size, gid.xy());
+}
+
+/// Runs `tile::STEPS` sub-steps from workgroup memory, reading from the grid and writing
+/// to the scratch grid so that neighbouring workgroups never see each other's writes
+#[spirv(compute(threads(16, 16)))]
+pub fn main_tiled_cs(
+    #[spirv(workgroup_id)] wid: UVec3,
+    #[spirv(local_invocation_index)] lid: u32,
+    #[cfg(not(feature = "constant-buffer"))]
+    #[spirv(push_constant)]
+    constants: &ComputeConstants,
+    #[cfg(feature = "constant-buffer")]
+    #[spirv(uniform, descriptor_set = 3, binding = 0)]
+    constants: &ComputeConstants,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] grid_buffer: &[Particle],
+    #[spirv(storage_buffer, descriptor_set = 2, binding = 0)] out_buffer: &mut [Particle],
+    #[spirv(workgroup)] cells: &mut [Particle; tile::REGION_LEN],
+) {
+    const THREADS: u32 = 16 * 16;
+    let size = constants.size;
+    let origin = tile::region_origin(wid.xy());
+
+    let mut i = lid;
+    while i < tile::REGION_LEN as u32 {
+        let pos = origin + tile::region_cell(i).as_ivec2();
+        cells[i as usize] = if tile::in_grid(pos, size) {
+            grid_buffer[(pos.y as u32 * size.x + pos.x as u32) as usize]
+        } else {
+            Particle::empty_from_tone(0.0)
+        };
+        i += THREADS;
+    }
+    unsafe { workgroup_memory_barrier_with_group_sync() };
+
+    let mut step = 0;
+    while step < tile::STEPS {
+        let offset = (constants.offset + step) % 2;
+        let mut block = lid;
+        while block < tile::BLOCKS {
+            tile::step_region_block(&mut tile::TileRef::new(cells), origin, size, offset, block);
+            block += THREADS;
+        }
+        unsafe { workgroup_memory_barrier_with_group_sync() };
+        step += 1;
+    }
+
+    let mut i = lid;
+    while i < tile::TILE_LEN as u32 {
+        let local = tile::tile_cell(i);
+        let pos = origin + local.as_ivec2();
+        if tile::in_grid(pos, size) {
+            out_buffer[(pos.y as u32 * size.x + pos.x as u32) as usize] =
+                cells[(local.y * tile::REGION + local.x) as usize];
+        }
+        i += THREADS;
+    }
+}
+
+#[spirv(compute(threads(16, 16)))]
+pub fn count_cs(
+    #[spirv(global_invocation_id)] gid: UVec3,
+    #[spirv(local_invocation_index)] lid: u32,
+    #[cfg(not(feature = "constant-buffer"))]
+    #[spirv(push_constant)]
+    constants: &ComputeConstants,
+    #[cfg(feature = "constant-buffer")]
+    #[spirv(uniform, descriptor_set = 3, binding = 0)]
+    constants: &ComputeConstants,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] grid_buffer: &[Particle],
+    #[spirv(storage_buffer, descriptor_set = 1, binding = 0)] counts: &mut [u32],
+    #[spirv(workgroup)] local_counts: &mut [u32; ParticleType::COUNT],
+) {
+    let lid = lid as usize;
+    if lid < ParticleType::COUNT {
+        local_counts[lid] = 0;
+    }
+    unsafe { workgroup_memory_barrier_with_group_sync() };
+
+    let size = constants.size;
+    if gid.x < size.x && gid.y < size.y {
+        let grid = GridRef::new(size.x as usize, size.y as usize, grid_buffer);
+        let behaviour = grid.get(gid.x as usize, gid.y as usize).behaviour() as usize;
+        // Cells of no material, which only a bad grid has, are not counted
+        if behaviour < ParticleType::COUNT {
+            unsafe {
+                atomic_i_add::<u32, { Scope::Workgroup as u32 }, { Semantics::NONE.bits() }>(
+                    &mut local_counts[behaviour],
+                    1,
+                );
+            }
+        }
+    }
+    unsafe { workgroup_memory_barrier_with_group_sync() };
+
+    if lid < ParticleType::COUNT {
+        unsafe {
+            atomic_i_add::<u32, { Scope::Device as u32 }, { Semantics::NONE.bits() }>(
+                &mut counts[lid],
+                local_counts[lid],
+            );
+        }
+    }
 }
@@ -0,0 +1,293 @@
+//! Cellular automata other than the falling sand. Rather than in the 2x2 Margolus blocks
+//! of `update`, all of their cells are stepped at once from the 3x3 Moore neighbourhood
+//! around them, reading the grid and writing a copy of it. Each has its own cell type,
+//! stored in the `u32` of a grid cell.
+
+use crate::gridref::GridRef;
+use crate::particle::rgb;
+use spirv_std::glam::*;
+
+/// The 3x3 cells around a cell, indexed as `[y][x]` with the cell itself at `[1][1]`.
+/// Cells outside the grid read as zero bits.
+pub type Neighbourhood<C> = [[C; 3]; 3];
+
+pub trait Automaton: Copy {
+    /// Decodes a cell, any bits are valid
+    fn from_bits(bits: u32) -> Self;
+
+    fn to_bits(self) -> u32;
+
+    /// Next state of the cell at the centre of `neighbourhood`
+    fn next(neighbourhood: Neighbourhood<Self>) -> Self;
+
+    fn color(self) -> Vec3;
+
+    /// Light given off on top of the colour, picked up by the bloom pass
+    fn emissive(self) -> f32 {
+        0.0
+    }
+}
+
+/// Next state of the cell at `pos` of `grid`
+pub fn step_cell<A: Automaton>(grid: &GridRef<u32>, size: UVec2, pos: UVec2) -> A {
+    A::next(neighbourhood(grid, size, pos, A::from_bits))
+}
+
+/// The cells around `pos` of `grid`, decoded with `decode`
+pub fn neighbourhood<T: Copy, A: Automaton>(
+    grid: &GridRef<T>,
+    size: UVec2,
+    pos: UVec2,
+    decode: impl Fn(T) -> A,
+) -> Neighbourhood<A> {
+    let mut neighbourhood = [[A::from_bits(0); 3]; 3];
+    let mut y = 0;
+    while y < 3 {
+        let mut x = 0;
+        while x < 3 {
+            let p = pos.as_ivec2() + ivec2(x as i32 - 1, y as i32 - 1);
+            if p.cmpge(IVec2::ZERO).all() && p.cmplt(size.as_ivec2()).all() {
+                neighbourhood[y][x] = decode(grid.get(p.x as usize, p.y as usize));
+            }
+            x += 1;
+        }
+        y += 1;
+    }
+    neighbourhood
+}
+
+/// Number of the eight neighbours that are in `state`
+fn count<A: Automaton + PartialEq>(neighbourhood: &Neighbourhood<A>, state: A) -> u32 {
+    let mut count = 0;
+    let mut i = 0;
+    while i < 9 {
+        if i != 4 && neighbourhood[i / 3][i % 3] == state {
+            count += 1;
+        }
+        i += 1;
+    }
+    count
+}
+
+/// Conway's Game of Life, B3/S23
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[repr(u32)]
+pub enum Life {
+    Dead,
+    Alive,
+}
+
+impl Automaton for Life {
+    fn from_bits(bits: u32) -> Self {
+        if bits & 1 == 1 {
+            Self::Alive
+        } else {
+            Self::Dead
+        }
+    }
+
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+
+    fn next(neighbourhood: Neighbourhood<Self>) -> Self {
+        match (neighbourhood[1][1], count(&neighbourhood, Self::Alive)) {
+            (Self::Alive, 2 | 3) | (Self::Dead, 3) => Self::Alive,
+            _ => Self::Dead,
+        }
+    }
+
+    fn color(self) -> Vec3 {
+        match self {
+            Self::Dead => rgb(0x1B1D22),
+            Self::Alive => rgb(0xE8E6E3),
+        }
+    }
+}
+
+/// Wireworld, where electrons travel along conductors
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[repr(u32)]
+pub enum Wireworld {
+    Empty,
+    Head,
+    Tail,
+    Conductor,
+}
+
+impl Automaton for Wireworld {
+    fn from_bits(bits: u32) -> Self {
+        match bits & 3 {
+            1 => Self::Head,
+            2 => Self::Tail,
+            3 => Self::Conductor,
+            _ => Self::Empty,
+        }
+    }
+
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+
+    fn next(neighbourhood: Neighbourhood<Self>) -> Self {
+        match neighbourhood[1][1] {
+            Self::Empty => Self::Empty,
+            Self::Head => Self::Tail,
+            Self::Tail => Self::Conductor,
+            Self::Conductor => match count(&neighbourhood, Self::Head) {
+                1 | 2 => Self::Head,
+                _ => Self::Conductor,
+            },
+        }
+    }
+
+    fn color(self) -> Vec3 {
+        match self {
+            Self::Empty => rgb(0x1B1D22),
+            Self::Head => rgb(0x5AB4FF),
+            Self::Tail => rgb(0xFF5A3C),
+            Self::Conductor => rgb(0xD9A441),
+        }
+    }
+
+    fn emissive(self) -> f32 {
+        match self {
+            Self::Head => 1.0,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Brian's Brain, where cells fire when exactly two neighbours fire and then rest a step
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[repr(u32)]
+pub enum BriansBrain {
+    Off,
+    On,
+    Dying,
+}
+
+impl Automaton for BriansBrain {
+    fn from_bits(bits: u32) -> Self {
+        match bits & 3 {
+            1 => Self::On,
+            2 => Self::Dying,
+            _ => Self::Off,
+        }
+    }
+
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+
+    fn next(neighbourhood: Neighbourhood<Self>) -> Self {
+        match neighbourhood[1][1] {
+            Self::On => Self::Dying,
+            Self::Dying => Self::Off,
+            Self::Off if count(&neighbourhood, Self::On) == 2 => Self::On,
+            Self::Off => Self::Off,
+        }
+    }
+
+    fn color(self) -> Vec3 {
+        match self {
+            Self::Off => rgb(0x1B1D22),
+            Self::On => rgb(0xFFFFFF),
+            Self::Dying => rgb(0x3D6FD1),
+        }
+    }
+
+    fn emissive(self) -> f32 {
+        match self {
+            Self::On => 0.5,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Langton's ant. An ant on a white cell turns right, on a black cell left, then flips
+/// the colour of the cell and moves forward. Ants moving into the same cell merge.
+///
+/// | bits | field                                              |
+/// |------|----------------------------------------------------|
+/// | 0    | black                                              |
+/// | 1-3  | ant on the cell, as 1 + up, right, down or left    |
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[repr(transparent)]
+pub struct LangtonsAnt(u32);
+
+const BLACK_BIT: u32 = 1;
+const ANT_SHIFT: u32 = 1;
+const ANT_MASK: u32 = 7 << ANT_SHIFT;
+/// Steps in the directions an ant can face, clockwise from up
+const DIRECTIONS: [IVec2; 4] = [IVec2::NEG_Y, IVec2::X, IVec2::Y, IVec2::NEG_X];
+
+impl LangtonsAnt {
+    pub const WHITE: Self = Self(0);
+    pub const BLACK: Self = Self(BLACK_BIT);
+    /// An ant facing up on a white cell
+    pub const ANT: Self = Self(1 << ANT_SHIFT);
+
+    pub fn black(self) -> bool {
+        self.0 & BLACK_BIT != 0
+    }
+
+    /// Direction of the ant on the cell as an index into `DIRECTIONS`, if there is one
+    pub fn ant(self) -> Option<u32> {
+        match (self.0 & ANT_MASK) >> ANT_SHIFT {
+            0 => None,
+            ant => Some((ant - 1) % 4),
+        }
+    }
+}
+
+impl Automaton for LangtonsAnt {
+    fn from_bits(bits: u32) -> Self {
+        Self(bits & (BLACK_BIT | ANT_MASK))
+    }
+
+    fn to_bits(self) -> u32 {
+        self.0
+    }
+
+    fn next(neighbourhood: Neighbourhood<Self>) -> Self {
+        let cell = neighbourhood[1][1];
+        // The ant on the cell flips it on its way out
+        let mut bits = (cell.black() != cell.ant().is_some()) as u32;
+        let mut direction = 0;
+        while direction < 4 {
+            let from = IVec2::ONE - DIRECTIONS[direction];
+            let neighbour = neighbourhood[from.y as usize][from.x as usize];
+            if let Some(ant) = neighbour.ant() {
+                let turned = (if neighbour.black() { ant + 3 } else { ant + 1 }) % 4;
+                if turned == direction as u32 {
+                    bits = (bits & BLACK_BIT) | (turned + 1) << ANT_SHIFT;
+                }
+            }
+            direction += 1;
+        }
+        Self(bits)
+    }
+
+    fn color(self) -> Vec3 {
+        if self.ant().is_some() {
+            rgb(0xE53935)
+        } else if self.black() {
+            rgb(0x1B1D22)
+        } else {
+            rgb(0xE8E6E3)
+        }
+    }
+
+    fn emissive(self) -> f32 {
+        if self.ant().is_some() {
+            0.5
+        } else {
+            0.0
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Wireworld electronics among the sand and water. Conductors, electron heads and electron
+//! tails are left in place by the Margolus `update`, and after every run of `RUN` of its
+//! sub-steps they take `STEPS_PER_RUN` Wireworld steps from their Moore neighbourhood,
+//! see `automata::Wireworld`. The runs are counted from the start and span whole tiled
+//! dispatches, so the tiled shader steps aligned runs with the same result as the
+//! untiled one.
+//! Water conducts as well and carries its charge along in the lifetime bits, so a puddle
+//! touching two wires shorts them.
+
+use crate::automata::{self, Automaton, Wireworld};
+use crate::gridref::GridRef;
+use crate::particle::*;
+use crate::tile;
+use spirv_std::glam::*;
+
+/// Sub-steps of the sand in a run, after which the electronics step
+pub const RUN: u32 = 4;
+/// Wireworld steps the electronics take after every run
+pub const STEPS_PER_RUN: u32 = 2;
+
+// A tiled dispatch must not end inside a run
+const _: () = assert!(RUN % tile::STEPS == 0);
+
+/// Lifetime of water carrying an electron head, counting down through the tail
+pub const HEAD_CHARGE: u32 = 2;
+pub const TAIL_CHARGE: u32 = 1;
+
+const WATER: u32 = ParticleType::Water as u32;
+const CONDUCTOR: u32 = ParticleType::Conductor as u32;
+const ELECTRON_HEAD: u32 = ParticleType::ElectronHead as u32;
+const ELECTRON_TAIL: u32 = ParticleType::ElectronTail as u32;
+
+/// Wireworld state of a cell, empty for cells that do not conduct
+pub fn state(particle: Particle) -> Wireworld {
+    match particle.behaviour() {
+        CONDUCTOR => Wireworld::Conductor,
+        ELECTRON_HEAD => Wireworld::Head,
+        ELECTRON_TAIL => Wireworld::Tail,
+        WATER => match particle.lifetime() {
+            HEAD_CHARGE => Wireworld::Head,
+            TAIL_CHARGE => Wireworld::Tail,
+            _ => Wireworld::Conductor,
+        },
+        _ => Wireworld::Empty,
+    }
+}
+
+fn with_state(mut particle: Particle, state: Wireworld) -> Particle {
+    if particle.behaviour() == WATER {
+        particle.set_lifetime(match state {
+            Wireworld::Head => HEAD_CHARGE,
+            Wireworld::Tail => TAIL_CHARGE,
+            _ => 0,
+        });
+    } else {
+        particle.set_behaviour(match state {
+            Wireworld::Head => ELECTRON_HEAD,
+            Wireworld::Tail => ELECTRON_TAIL,
+            _ => CONDUCTOR,
+        });
+    }
+    particle
+}
+
+/// The cell at `pos` of `grid` after one Wireworld step
+pub fn step_cell(grid: &GridRef<Particle>, size: UVec2, pos: UVec2) -> Particle {
+    let particle = grid.get(pos.x as usize, pos.y as usize);
+    if state(particle) == Wireworld::Empty {
+        return particle;
+    }
+    let next = Wireworld::next(automata::neighbourhood(grid, size, pos, state));
+    with_state(particle, next)
+}
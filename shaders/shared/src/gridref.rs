@@ -19,6 +19,19 @@ impl<'a, T: Copy> GridRef<'a, T> {
     }
 }
 
+/// Cell access shared by grids in storage buffers and tiles in workgroup memory
+pub trait Cells<T: Copy> {
+    fn get(&self, x: usize, y: usize) -> T;
+
+    fn set(&mut self, x: usize, y: usize, value: T);
+
+    fn swap(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        let tmp = self.get(x0, y0);
+        self.set(x0, y0, self.get(x1, y1));
+        self.set(x1, y1, tmp);
+    }
+}
+
 pub struct GridRefMut<'a, T> {
     w: usize,
     h: usize,
@@ -52,3 +65,17 @@ impl<'a, T: Copy> GridRefMut<'a, T> {
         self.buffer[y1 * self.w + x1] = tmp;
     }
 }
+
+impl<T: Copy> Cells<T> for GridRefMut<'_, T> {
+    fn get(&self, x: usize, y: usize) -> T {
+        GridRefMut::get(self, x, y)
+    }
+
+    fn set(&mut self, x: usize, y: usize, value: T) {
+        GridRefMut::set(self, x, y, value)
+    }
+
+    fn swap(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        GridRefMut::swap(self, x0, y0, x1, y1)
+    }
+}
@@ -2,12 +2,13 @@
 
 pub use noise::simplex::noise as rand;
 
+pub mod automata;
+pub mod electrics;
 #[cfg(not(target_arch = "spirv"))]
 pub mod grid;
 pub mod gridref;
 pub mod noise;
 pub mod particle;
 pub mod push_constants;
-
-pub const UI_MENU_HEIGHT: u32 = 22;
-pub const UI_SIDEBAR_WIDTH: u32 = 164;
+pub mod tile;
+pub mod update;
@@ -1,25 +1,40 @@
+use crate::electrics::{HEAD_CHARGE, TAIL_CHARGE};
 use bytemuck::{Pod, Zeroable};
 use spirv_std::glam::*;
 
-fn rgb(x: u32) -> Vec3 {
+pub(crate) fn rgb(x: u32) -> Vec3 {
     uvec3(x >> 16, (x >> 8) & 0xFF, x & 0xFF).as_vec3() / 255.0
 }
 
 #[derive(Default, PartialEq, Clone, Copy)]
+#[cfg_attr(
+    not(target_arch = "spirv"),
+    derive(Debug, serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u32)]
 pub enum ParticleType {
     #[default]
     Empty,
     Sand,
     Water,
+    /// Wireworld electronics, which stay in place, see `electrics`
+    Conductor,
+    ElectronHead,
+    ElectronTail,
 }
 
 impl ParticleType {
+    pub const COUNT: usize = 6;
+
     pub fn from_value(value: u32) -> Self {
         match value {
             0 => Self::Empty,
             1 => Self::Sand,
             2 => Self::Water,
+            3 => Self::Conductor,
+            4 => Self::ElectronHead,
+            5 => Self::ElectronTail,
             _ => panic!("Invalid value"),
         }
     }
@@ -29,32 +44,131 @@ impl ParticleType {
             Self::Empty => (rgb(0xE8E6E3), rgb(0xDDDDEE)),
             Self::Sand => (rgb(0xDDC594), rgb(0xC2B47C)),
             Self::Water => (rgb(0x428EF1), rgb(0x24B6FF)),
+            Self::Conductor => (rgb(0xB87333), rgb(0xA0612A)),
+            Self::ElectronHead => (rgb(0x5AB4FF), rgb(0x8FCBFF)),
+            Self::ElectronTail => (rgb(0xFF6A3C), rgb(0xE8552E)),
+        }
+    }
+
+    /// Light given off on top of the material colour. Anything above zero pushes the
+    /// colour past 1.0, which is what the bloom pass picks up.
+    pub fn emissive(&self) -> f32 {
+        match self {
+            Self::Empty => 0.0,
+            Self::Sand => 0.0,
+            Self::Water => 0.0,
+            Self::Conductor => 0.0,
+            Self::ElectronHead => 1.5,
+            Self::ElectronTail => 0.3,
         }
     }
 }
 
+const MATERIAL_MASK: u32 = 0xFF;
+const TONE_SHIFT: u32 = 8;
+const TONE_MASK: u32 = 0xFF << TONE_SHIFT;
+const LIFETIME_SHIFT: u32 = 16;
+const LIFETIME_MASK: u32 = 0xFF << LIFETIME_SHIFT;
+const MOVED: u32 = 1 << 24;
+const FLAGS_MASK: u32 = MOVED;
+
+/// A cell packed into 32 bits, to keep grid bandwidth down.
+///
+/// | bits  | field                          |
+/// |-------|--------------------------------|
+/// | 0-7   | material id                    |
+/// | 8-15  | tone, 0 to 1 in steps of 1/255 |
+/// | 16-23 | lifetime, the charge of water  |
+/// | 24    | moved in the last step         |
 #[derive(Clone, Copy, Pod, Zeroable)]
-#[repr(C)]
-pub struct Particle {
-    pub behaviour: u32,
-    pub tone: f32,
-}
+#[repr(transparent)]
+pub struct Particle(u32);
 
 impl Particle {
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub fn to_bits(self) -> u32 {
+        self.0
+    }
+
+    pub fn behaviour(&self) -> u32 {
+        self.0 & MATERIAL_MASK
+    }
+
+    pub fn set_behaviour(&mut self, behaviour: u32) {
+        self.0 = (self.0 & !MATERIAL_MASK) | (behaviour & MATERIAL_MASK);
+    }
+
+    pub fn tone(&self) -> f32 {
+        ((self.0 & TONE_MASK) >> TONE_SHIFT) as f32 / 255.0
+    }
+
+    pub fn set_tone(&mut self, tone: f32) {
+        let tone = (tone.clamp(0.0, 1.0) * 255.0 + 0.5) as u32;
+        self.0 = (self.0 & !TONE_MASK) | (tone << TONE_SHIFT);
+    }
+
+    pub fn lifetime(&self) -> u32 {
+        (self.0 & LIFETIME_MASK) >> LIFETIME_SHIFT
+    }
+
+    /// Saturates at 255
+    pub fn set_lifetime(&mut self, lifetime: u32) {
+        self.0 = (self.0 & !LIFETIME_MASK) | (lifetime.min(255) << LIFETIME_SHIFT);
+    }
+
+    pub fn moved(&self) -> bool {
+        self.0 & MOVED != 0
+    }
+
+    pub fn set_moved(&mut self, moved: bool) {
+        self.0 = if moved {
+            self.0 | MOVED
+        } else {
+            self.0 & !MOVED
+        };
+    }
+
+    /// Whether both are the same particle, ignoring the flags
+    pub fn same(&self, other: Self) -> bool {
+        (self.0 ^ other.0) & !FLAGS_MASK == 0
+    }
+
+    /// Material the cell is drawn as. Charged water looks like the electron it carries.
+    fn appearance(&self) -> ParticleType {
+        let material = ParticleType::from_value(self.behaviour());
+        match (material, self.lifetime()) {
+            (ParticleType::Water, HEAD_CHARGE) => ParticleType::ElectronHead,
+            (ParticleType::Water, TAIL_CHARGE) => ParticleType::ElectronTail,
+            _ => material,
+        }
+    }
+
     fn color_range(&self) -> (Vec3, Vec3) {
-        ParticleType::from_value(self.behaviour).color_range()
+        self.appearance().color_range()
+    }
+
+    pub fn emissive(&self) -> f32 {
+        self.appearance().emissive()
     }
 
     pub fn color(&self) -> Vec3 {
         let (c1, c2) = self.color_range();
-        c1.lerp(c2, self.tone)
+        c1.lerp(c2, self.tone())
+    }
+
+    /// Colour of the material, ignoring the tone
+    pub fn flat_color(&self) -> Vec3 {
+        let (c1, c2) = self.color_range();
+        c1.lerp(c2, 0.5)
     }
 
     pub fn new_from_tone(behaviour: ParticleType, tone: f32) -> Self {
-        Self {
-            behaviour: behaviour as u32,
-            tone,
-        }
+        let mut particle = Self(behaviour as u32);
+        particle.set_tone(tone);
+        particle
     }
 
     pub fn sand_from_tone(tone: f32) -> Self {
@@ -66,13 +180,43 @@ impl Particle {
     }
 }
 
+#[cfg(not(target_arch = "spirv"))]
+impl ParticleType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Empty => "Empty",
+            Self::Sand => "Sand",
+            Self::Water => "Water",
+            Self::Conductor => "Conductor",
+            Self::ElectronHead => "Electron Head",
+            Self::ElectronTail => "Electron Tail",
+        }
+    }
+
+    pub fn iter() -> impl Iterator<Item = Self> {
+        (0..Self::COUNT as u32).map(Self::from_value)
+    }
+}
+
 #[cfg(not(target_arch = "spirv"))]
 impl Particle {
+    /// Named values of every field, for display in the inspector
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            (
+                "Material",
+                ParticleType::from_value(self.behaviour())
+                    .name()
+                    .to_string(),
+            ),
+            ("Tone", format!("{:.3}", self.tone())),
+            ("Lifetime", self.lifetime().to_string()),
+            ("Moved", self.moved().to_string()),
+        ]
+    }
+
     pub fn new(behaviour: ParticleType) -> Self {
-        Self {
-            behaviour: behaviour as u32,
-            tone: rand::random(),
-        }
+        Self::new_from_tone(behaviour, rand::random())
     }
 
     pub fn new_sand() -> Self {
@@ -1,19 +1,33 @@
-use super::{Bool, Size, Vec2};
+//! The constants are push constants or, with the `constant-buffer` feature of the
+//! shaders, read from a uniform buffer. The uniform layout rules align every struct
+//! member to 16 bytes, so the constants only hold scalars and vectors, each on a multiple
+//! of its alignment, and lay out the same both ways.
+
 use bytemuck::{Pod, Zeroable};
+use spirv_std::glam::{UVec2, Vec2};
 
+/// Used by the fragment shader and by the paint and colour compute passes
 #[derive(Copy, Clone, Pod, Zeroable)]
 #[repr(C)]
 pub struct FragmentConstants {
-    pub size: Size,
+    pub size: UVec2,
+    /// Top left of the simulation viewport, in physical pixels
+    pub viewport_offset: Vec2,
+    pub viewport_size: Vec2,
     pub cursor: Vec2,
     pub prev_cursor: Vec2,
     pub time: f32,
-    pub cursor_down: Bool,
+    /// Non-zero while painting
+    pub cursor_down: u32,
     pub current_particle_type: u32,
     pub brush_size_sq: f32,
     pub offset: u32,
     pub zoom: f32,
-    pub debug: Bool,
+    /// Non-zero in debug mode
+    pub debug: u32,
+    pub render_mode: u32,
+    /// Material left in colour by [`RenderMode::Isolate`]
+    pub isolated_material: u32,
 }
 
 impl FragmentConstants {
@@ -25,7 +39,7 @@ impl FragmentConstants {
 #[derive(Copy, Clone, Pod, Zeroable)]
 #[repr(C)]
 pub struct ComputeConstants {
-    pub size: Size,
+    pub size: UVec2,
     pub time: f32,
     pub offset: u32,
     pub zoom: f32,
@@ -36,3 +50,68 @@ impl ComputeConstants {
         core::mem::size_of::<Self>()
     }
 }
+
+#[derive(Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct BloomConstants {
+    /// Size of a texel of the sampled texture, in uv units
+    pub texel_size: Vec2,
+    /// Size of a pixel of the render target, in uv units
+    pub pixel_size: Vec2,
+    pub strength: f32,
+}
+
+impl BloomConstants {
+    pub fn mem_size() -> usize {
+        core::mem::size_of::<Self>()
+    }
+}
+
+/// How the fragment shader colours cells, only used in debug mode
+#[derive(Default, PartialEq, Clone, Copy)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[repr(u32)]
+pub enum RenderMode {
+    #[default]
+    Normal,
+    /// Material colours without per particle tone
+    Flat,
+    /// Cells that changed in the last step
+    Activity,
+    /// Checkerboard of the Margolus blocks of the current offset
+    BlockParity,
+    /// Only the isolated material is coloured
+    Isolate,
+}
+
+impl RenderMode {
+    pub const COUNT: usize = 5;
+
+    pub fn from_value(value: u32) -> Self {
+        match value {
+            0 => Self::Normal,
+            1 => Self::Flat,
+            2 => Self::Activity,
+            3 => Self::BlockParity,
+            4 => Self::Isolate,
+            _ => panic!("Invalid value"),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "spirv"))]
+impl RenderMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Normal => "Normal",
+            Self::Flat => "Flat",
+            Self::Activity => "Activity",
+            Self::BlockParity => "Block Parity",
+            Self::Isolate => "Isolate",
+        }
+    }
+
+    pub fn iter() -> impl Iterator<Item = Self> {
+        (0..Self::COUNT as u32).map(Self::from_value)
+    }
+}
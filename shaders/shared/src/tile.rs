@@ -0,0 +1,85 @@
+//! Geometry of the tiled compute path. Each workgroup loads its tile plus a halo into
+//! workgroup memory, runs `STEPS` sub-steps there and writes back only the tile.
+//!
+//! A Margolus sub-step only moves particles within a block, so a cell can only be
+//! wrong after a sub-step if one of its neighbours was wrong before it. Cells missing
+//! from the edge of the region therefore corrupt at most one more ring of the halo per
+//! sub-step, leaving the tile exact as long as the halo is `STEPS` cells wide.
+//!
+//! A 16x16 workgroup owns a 32x32 tile rather than a 16x16 one, one invocation per
+//! Margolus block of the tile. The halo is loaded and stepped again by every tile that
+//! borders it, which makes small tiles expensive: a 16x16 tile loads a 24x24 region,
+//! 2.25 cells loaded and stepped per cell written, while a 32x32 tile loads a 40x40
+//! region, 1.56 per cell written. The region takes 6400 bytes of workgroup memory,
+//! well within the 16 KiB every device offers. `cargo bench -p runner` times the tiled
+//! path against the untiled one.
+
+use crate::gridref::Cells;
+use crate::particle::Particle;
+use crate::update::step_block;
+use spirv_std::glam::*;
+
+/// Side of the tile written back by one 16x16 workgroup, in cells
+pub const TILE: u32 = 32;
+/// Sub-steps run per dispatch, which is also the width of the halo
+pub const STEPS: u32 = 4;
+/// Side of the tile plus its halo
+pub const REGION: u32 = TILE + 2 * STEPS;
+pub const REGION_LEN: usize = (REGION * REGION) as usize;
+pub const TILE_LEN: usize = (TILE * TILE) as usize;
+/// Blocks per sub-step in the region
+pub const BLOCKS: u32 = (REGION / 2) * (REGION / 2);
+
+pub struct TileRef<'a> {
+    cells: &'a mut [Particle; REGION_LEN],
+}
+
+impl<'a> TileRef<'a> {
+    pub fn new(cells: &'a mut [Particle; REGION_LEN]) -> Self {
+        Self { cells }
+    }
+}
+
+impl Cells<Particle> for TileRef<'_> {
+    fn get(&self, x: usize, y: usize) -> Particle {
+        self.cells[y * REGION as usize + x]
+    }
+
+    fn set(&mut self, x: usize, y: usize, value: Particle) {
+        self.cells[y * REGION as usize + x] = value;
+    }
+}
+
+/// Grid position of the top left cell of the region of a workgroup
+pub fn region_origin(workgroup: UVec2) -> IVec2 {
+    (workgroup * TILE).as_ivec2() - STEPS as i32
+}
+
+/// Position in the region of the `i`th cell of the region
+pub fn region_cell(i: u32) -> UVec2 {
+    uvec2(i % REGION, i / REGION)
+}
+
+/// Position in the region of the `i`th cell of the tile
+pub fn tile_cell(i: u32) -> UVec2 {
+    uvec2(i % TILE, i / TILE) + STEPS
+}
+
+pub fn in_grid(pos: IVec2, size: UVec2) -> bool {
+    pos.cmpge(IVec2::ZERO).all() && pos.cmplt(size.as_ivec2()).all()
+}
+
+/// Steps the `block`th block of the region, skipping blocks that `main_cs` would not
+/// run because they stick out of the grid or the region
+pub fn step_region_block(tile: &mut TileRef, origin: IVec2, size: UVec2, offset: u32, block: u32) {
+    let side = REGION / 2;
+    let pos = uvec2(block % side, block / side) * 2 + offset;
+    let global = origin + pos.as_ivec2();
+    if pos.x + 1 < REGION
+        && pos.y + 1 < REGION
+        && in_grid(global, size)
+        && in_grid(global + 1, size)
+    {
+        step_block(pos, tile);
+    }
+}
@@ -1,6 +1,6 @@
+use crate::gridref::*;
+use crate::particle::*;
 use seq_macro::seq;
-use shared::gridref::*;
-use shared::particle::*;
 use spirv_std::glam::*;
 
 const EMPTY: u32 = 0;
@@ -12,13 +12,40 @@ const TOP_RIGHT: [usize; 2] = [1, 0];
 const BOT_LEFT: [usize; 2] = [0, 1];
 const BOT_RIGHT: [usize; 2] = [1, 1];
 
-pub fn update(pos: UVec2, grid: &mut GridRefMut<Particle>) {
+/// Updates the 2x2 block at `pos` and flags the cells that changed as moved
+pub fn step_block(pos: UVec2, grid: &mut impl Cells<Particle>) {
+    let corners = [UVec2::ZERO, UVec2::X, UVec2::Y, UVec2::ONE];
+    let mut before = [Particle::empty_from_tone(0.0); 4];
+    let mut i = 0;
+    while i < 4 {
+        let p = pos + corners[i];
+        before[i] = grid.get(p.x as usize, p.y as usize);
+        i += 1;
+    }
+
+    update(pos, grid);
+
+    let mut i = 0;
+    while i < 4 {
+        let p = pos + corners[i];
+        let mut after = grid.get(p.x as usize, p.y as usize);
+        let moved = !after.same(before[i]);
+        // Only written back when the flag changes, most cells are at rest
+        if moved != after.moved() {
+            after.set_moved(moved);
+            grid.set(p.x as usize, p.y as usize, after);
+        }
+        i += 1;
+    }
+}
+
+pub fn update(pos: UVec2, grid: &mut impl Cells<Particle>) {
     let x = pos.x as usize;
     let y = pos.y as usize;
-    let top_left = grid.get(x, y).behaviour;
-    let top_right = grid.get(x + 1, y).behaviour;
-    let bot_left = grid.get(x, y + 1).behaviour;
-    let bot_right = grid.get(x + 1, y + 1).behaviour;
+    let top_left = grid.get(x, y).behaviour();
+    let top_right = grid.get(x + 1, y).behaviour();
+    let bot_left = grid.get(x, y + 1).behaviour();
+    let bot_right = grid.get(x + 1, y + 1).behaviour();
 
     let mut swap = |p0: [usize; 2], p1: [usize; 2]| {
         grid.swap(x + p0[0], y + p0[1], x + p1[0], y + p1[1]);